@@ -1,10 +1,19 @@
 use actix_web::{
     dev::{ServiceFactory, ServiceRequest},
-    get, web, App, Error, HttpResponse, Responder,
+    get, post, web, App, Error, HttpResponse, Responder,
 };
 use actix_web_httpauth::middleware::HttpAuthentication;
+use mongodb::Client;
 
-use crate::{blockchain::BlockChain, middleware::auth::{user_validator, admin_validator}, user::utils::generate_keys};
+use crate::{
+    blockchain::{
+        services::{get_inclusion_proof, seal_pending_transactions, validate_blockchain},
+        types::{BlockchainError, GenesisMetadata},
+        utils::authority_public_key,
+        BlockChain,
+    },
+    middleware::auth::{admin_validator, user_validator},
+};
 
 #[get("")]
 async fn test() -> impl Responder {
@@ -15,10 +24,22 @@ async fn test() -> impl Responder {
     // let fakekey = generate_keys(&"password".into(),&"182830994".into());
     // let _ = t.sign(fakekey.sk);
 
-    let mut bc = BlockChain::new(Box::new(vec![pk.clone()]));
+    let genesis = GenesisMetadata {
+        election_id: "test".into(),
+        title: "Test".into(),
+        candidates: vec![],
+        scope: "GENERAL".into(),
+        scope_value: "".into(),
+        started_at: 0,
+        authority: authority_public_key(),
+        voting_mode: Default::default(),
+        encryption_key: None,
+        credential_key: None,
+    };
+    let bc = BlockChain::new(genesis.clone(), Box::new(vec![pk.clone()]));
     let s = bc.serialize();
 
-    let mut bc2 = BlockChain::new(Box::new(vec![]));
+    let mut bc2 = BlockChain::new(genesis, Box::new(vec![]));
     bc2.deserialize(s);
 
     let balance = bc.get_address_balance(&pk);
@@ -26,6 +47,33 @@ async fn test() -> impl Responder {
     HttpResponse::Ok().body("Testing blockchain")
 }
 
+#[post("/seal/{election_id}")]
+async fn handle_seal(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, BlockchainError> {
+    let election_id = path.into_inner();
+    seal_pending_transactions(client, election_id).await
+}
+
+#[get("/validate/{election_id}")]
+async fn handle_validate(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, BlockchainError> {
+    let election_id = path.into_inner();
+    validate_blockchain(client, election_id).await
+}
+
+#[get("/proof/{election_id}/{transaction_hash}")]
+async fn handle_get_proof(
+    client: web::Data<Client>,
+    path: web::Path<(String, String)>,
+) -> Result<String, BlockchainError> {
+    let (election_id, transaction_hash) = path.into_inner();
+    get_inclusion_proof(client, election_id, transaction_hash).await
+}
+
 pub fn register_controllers<
     T: ServiceFactory<ServiceRequest, Config = (), Error = Error, InitError = ()>,
 >(
@@ -34,6 +82,9 @@ pub fn register_controllers<
     app.service(
         web::scope("/blockchain")
             .service(test)
+            .service(handle_seal)
+            .service(handle_validate)
             .wrap(HttpAuthentication::bearer(admin_validator)),
     )
+    .service(web::scope("/chain").service(handle_get_proof))
 }
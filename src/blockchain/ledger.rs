@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Block, Transaction, VOID_ADDRESS};
+
+/// Account state derived from a chain: confirmed balances from sealed blocks and
+/// the amounts each address has committed in the transaction buffer. Kept up to
+/// date as blocks are appended so lookups never have to walk the chain.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Ledger {
+    balances: HashMap<String, i64>,
+    pending: HashMap<String, i64>,
+    next_nonces: HashMap<String, u64>,
+    /// Amounts each sender has given each recipient, sealed and pending.
+    sent: HashMap<String, HashMap<String, i64>>,
+    pending_sent: HashMap<String, HashMap<String, i64>>,
+}
+
+impl Ledger {
+    /// Rebuilds the state from scratch by replaying every block and pending transaction.
+    pub fn from_chain(chain: &[Block], transaction_buffer: &[Transaction]) -> Self {
+        let mut ledger = Ledger::default();
+        for block in chain {
+            ledger.apply_block(block);
+        }
+        for transaction in transaction_buffer {
+            ledger.add_pending(transaction);
+        }
+        ledger
+    }
+
+    pub fn apply_block(&mut self, block: &Block) {
+        for transaction in block.transactions.iter() {
+            *self.balances.entry(transaction.from.clone()).or_insert(0) -= transaction.amount;
+            *self.balances.entry(transaction.to.clone()).or_insert(0) += transaction.amount;
+            *self
+                .sent
+                .entry(transaction.from.clone())
+                .or_default()
+                .entry(transaction.to.clone())
+                .or_insert(0) += transaction.amount;
+            self.use_nonce(transaction);
+        }
+    }
+
+    pub fn add_pending(&mut self, transaction: &Transaction) {
+        *self.pending.entry(transaction.from.clone()).or_insert(0) += transaction.amount;
+        *self
+            .pending_sent
+            .entry(transaction.from.clone())
+            .or_default()
+            .entry(transaction.to.clone())
+            .or_insert(0) += transaction.amount;
+        self.use_nonce(transaction);
+    }
+
+    fn use_nonce(&mut self, transaction: &Transaction) {
+        if transaction.from == VOID_ADDRESS {
+            return;
+        }
+        let next = self.next_nonces.entry(transaction.from.clone()).or_insert(0);
+        *next = (*next).max(transaction.nonce + 1);
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+        self.pending_sent.clear();
+    }
+
+    /// Balance confirmed by sealed blocks.
+    pub fn balance(&self, address: &str) -> i64 {
+        *self.balances.get(address).unwrap_or(&0)
+    }
+
+    /// Total amount `address` is spending in transactions that are not sealed yet.
+    pub fn pending_amount(&self, address: &str) -> i64 {
+        *self.pending.get(address).unwrap_or(&0)
+    }
+
+    /// Next unused nonce for `address`, counting pending transactions as used.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        *self.next_nonces.get(address).unwrap_or(&0)
+    }
+
+    /// Total `from` has sent `to`, counting pending transactions.
+    pub fn sent(&self, from: &str, to: &str) -> i64 {
+        let amount = |sent: &HashMap<String, HashMap<String, i64>>| {
+            *sent.get(from).and_then(|s| s.get(to)).unwrap_or(&0)
+        };
+        amount(&self.sent) + amount(&self.pending_sent)
+    }
+
+    /// Number of senders behind the sealed balance of `to`.
+    pub fn senders_to(&self, to: &str) -> usize {
+        self.sent
+            .values()
+            .filter(|recipients| recipients.get(to).is_some_and(|amount| *amount > 0))
+            .count()
+    }
+
+    /// Confirmed balance less whatever is already pending.
+    pub fn available_balance(&self, address: &str) -> i64 {
+        self.balance(address) - self.pending_amount(address)
+    }
+}
@@ -1,6 +1,8 @@
 use std::{collections::HashMap, fmt::format, io::Read, mem, time::SystemTime};
 
 pub mod controllers;
+pub mod encoding;
+pub mod ledger;
 pub mod models;
 pub mod services;
 pub mod types;
@@ -11,79 +13,270 @@ use actix_web::{
     Error,
 };
 use chrono::Utc;
-use ed25519_compact::{SecretKey, Signature};
+use curve25519_dalek::{EdwardsPoint, RistrettoPoint, Scalar};
+use ed25519_compact::{KeyPair, PublicKey, SecretKey, Signature};
 use jwt::ToBase64;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256, Sha256VarCore};
 
 use crate::{
     common::utils::{from_base64, to_base64},
+    crypto::{
+        blind::{credential_message, Credential},
+        elgamal::Ciphertext,
+        edwards_from_hex, edwards_point, point_from_hex, point_to_hex,
+        proofs::{BallotContext, BallotProof},
+        ring::{key_image, RingSignature},
+        shuffle::EncryptedBallot,
+    },
     user::utils::pk_from_string,
 };
 
-use self::types::BlockchainError;
+use self::{
+    encoding::{
+        CanonicalEncoder, BLOCK_DOMAIN, CANONICAL_V1, CANONICAL_V2, CANONICAL_V3, CANONICAL_V4,
+        CANONICAL_V5, CANONICAL_VERSION, LEGACY_VERSION, TRANSACTION_DOMAIN,
+    },
+    ledger::Ledger,
+    types::{
+        BlockchainError, GenesisMetadata, MerkleProofStep, MixStage, SealingPolicy,
+        ValidationFailure, ValidationIssue, ValidationReport, VotingMode,
+    },
+    utils::{authority_key_for, merkle_proof, merkle_root, vote_commitment},
+};
 
 const VOID_ADDRESS: &'static str = "0000000000000000";
 const VOID_HASH: &'static str = "0000000000000000";
+/// Where ballots are sent; it never spends, so its balance is the number of ballots cast.
+const BALLOT_ADDRESS: &str = "BALLOT_BOX";
+/// Candidates an encrypted ballot selects.
+pub const ENCRYPTED_SELECTIONS: u64 = 1;
+
+/// A vote that carries more than a single recipient.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Ballot {
+    /// Candidates in order of preference, most preferred first.
+    Ranked { preferences: Vec<String> },
+    /// One ciphertext per candidate under the election key, encrypting 1 for
+    /// the chosen candidate and 0 for the rest, with a proof that it does.
+    Encrypted {
+        ciphertexts: Vec<Ciphertext>,
+        proof: BallotProof,
+    },
+    /// A hiding commitment to a candidate, cast while the election is ongoing.
+    Commitment { commitment: String },
+    /// Opens the sender's commitment once voting has closed.
+    Reveal { candidate: String, salt: String },
+}
+
+impl Ballot {
+    fn encode(&self, encoder: &mut CanonicalEncoder) {
+        match self {
+            Ballot::Ranked { preferences } => {
+                encoder.string("RANKED").strings(preferences);
+            }
+            Ballot::Encrypted { ciphertexts, proof } => {
+                encoder.string("ENCRYPTED").count(ciphertexts.len());
+                for ciphertext in ciphertexts {
+                    ciphertext.encode(encoder);
+                }
+                proof.encode(encoder);
+            }
+            Ballot::Commitment { commitment } => {
+                encoder.string("COMMITMENT").string(commitment);
+            }
+            Ballot::Reveal { candidate, salt } => {
+                encoder.string("REVEAL").string(candidate).string(salt);
+            }
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Transaction {
+    #[serde(default)]
+    pub chain_id: String,
     pub from: String,
     pub to: String,
     pub amount: i64,
     pub timestamp: i64,
+    #[serde(default)]
+    pub nonce: u64,
+    #[serde(default)]
+    pub ballot: Option<Ballot>,
+    /// Which encoding `calculate_hash` uses, see `encoding`.
+    #[serde(default)]
+    pub version: u8,
     signature: Option<String>,
+    /// Set instead of `signature` on anonymous votes, whose `from` is the key image.
+    #[serde(default)]
+    ring_signature: Option<RingSignature>,
+    /// The authority's blind signature on `from`, a one-time ballot key that is
+    /// on no voter roll.
+    #[serde(default)]
+    credential: Option<Credential>,
 }
 
 impl Transaction {
-    pub fn new(from: String, to: String, amount: i64) -> Self {
+    pub fn new(chain_id: String, from: String, to: String, amount: i64, nonce: u64) -> Self {
         Transaction {
+            version: CANONICAL_VERSION,
+            chain_id,
             from,
             to,
             amount,
             timestamp: Utc::now().timestamp(),
+            nonce,
+            ballot: None,
             signature: None,
+            ring_signature: None,
+            credential: None,
         }
     }
 
+    /// A transaction that spends the voter's token on `ballot`. Voters build and
+    /// sign their own transactions; this is what a client does.
+    #[allow(dead_code)]
+    pub fn new_ballot(chain_id: String, from: String, ballot: Ballot, nonce: u64) -> Self {
+        let mut transaction = Transaction::new(chain_id, from, BALLOT_ADDRESS.into(), 1, nonce);
+        transaction.ballot = Some(ballot);
+        transaction
+    }
+
+    /// An anonymous vote for `to`, ring signed as `ring[signer]` over the voter roll.
+    /// It is sent from the signer's key image, so a second vote reuses the address.
+    #[allow(dead_code)]
+    pub fn new_anonymous(
+        chain_id: String,
+        to: String,
+        ring: &[EdwardsPoint],
+        signer: usize,
+        secret: &Scalar,
+    ) -> Self {
+        let image = key_image(secret, &ring[signer]);
+        let mut transaction = Transaction::new(chain_id, point_to_hex(&image), to, 1, 0);
+        transaction.ring_signature = Some(RingSignature::sign(
+            &transaction.signing_message(),
+            ring,
+            signer,
+            secret,
+        ));
+        transaction
+    }
+
+    /// Whether the sender is kept off the voter roll, by a ring signature or a credential.
+    pub fn is_anonymous(&self) -> bool {
+        self.ring_signature.is_some() || self.credential.is_some()
+    }
+
+    #[allow(dead_code)]
     pub fn sign(&mut self, sk: SecretKey) -> Result<bool, BlockchainError> {
         let pk = sk.public_key().to_base64().unwrap().to_string();
         if pk != self.from {
-            println!("error my nigga");
             return Err(BlockchainError::TransactionError {
                 content: "Invalid Transaction:: Cannot sign transactions for other wallets".into(),
             });
         }
-        let hash = self.calculate_hash();
-        println!("{}", hash);
-        let sig = sk.sign(hash, None);
+        let sig = sk.sign(self.signing_message(), None);
         self.signature = Some(to_base64(&sig.to_vec()));
         Ok(true)
     }
 
-    pub fn calculate_hash(&self) -> String {
-        let hash_input = format!("{}{}{}{}", self.from, self.to, self.amount, self.timestamp);
+    /// The encoding hashed from version 1 on; legacy transactions hash display strings.
+    pub fn canonical_encoding(&self) -> Vec<u8> {
+        debug_assert!(
+            self.version >= CANONICAL_V1,
+            "legacy transactions have no canonical encoding"
+        );
+        let mut encoder = CanonicalEncoder::new(TRANSACTION_DOMAIN, self.version);
+        encoder
+            .string(&self.chain_id)
+            .string(&self.from)
+            .string(&self.to)
+            .i64(self.amount)
+            .i64(self.timestamp)
+            .u64(self.nonce);
+        if self.version >= CANONICAL_V2 {
+            encoder.presence(self.ballot.is_some());
+            if let Some(ballot) = &self.ballot {
+                ballot.encode(&mut encoder);
+            }
+        }
+        encoder.finish()
+    }
+
+    fn digest(&self) -> Vec<u8> {
         let mut hash = Sha256::new();
-        hash.update(hash_input);
-        format!("{:X}", hash.finalize())
+        if self.version == LEGACY_VERSION {
+            hash.update(format!(
+                "{}{}{}{}{}{}",
+                self.chain_id, self.from, self.to, self.amount, self.timestamp, self.nonce
+            ));
+        } else {
+            hash.update(self.canonical_encoding());
+        }
+        hash.finalize().to_vec()
+    }
+
+    pub fn calculate_hash(&self) -> String {
+        hex::encode_upper(self.digest())
+    }
+
+    /// What the sender signs: the raw digest, or its hex string for legacy transactions.
+    pub fn signing_message(&self) -> Vec<u8> {
+        if self.version == LEGACY_VERSION {
+            self.calculate_hash().into_bytes()
+        } else {
+            self.digest()
+        }
     }
 
     pub fn is_valid(&self) -> Result<bool, BlockchainError> {
+        if self.version > CANONICAL_VERSION {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Unsupported encoding version".into(),
+            });
+        }
+        if self.ballot.is_some() && self.version < CANONICAL_V2 {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ballot is not covered by the transaction hash"
+                    .into(),
+            });
+        }
         if self.from == VOID_ADDRESS.to_string() {
             return Ok(true);
         }
+        // ring membership is checked by the chain, which holds the voter roll
+        if let Some(ring_signature) = &self.ring_signature {
+            return Ok(self.from == point_to_hex(&ring_signature.key_image));
+        }
         if self.signature == None {
             return Err(BlockchainError::TransactionError {
                 content: "Invalid Transaction:: Empty signature".into(),
             });
         }
 
-        let pk = pk_from_string(&self.from);
-        let sig_array = from_base64(self.signature.as_ref().unwrap()).unwrap();
-
-        let sig = Signature::from_slice(sig_array.as_slice()).unwrap();
+        let pk = match pk_from_string(&self.from) {
+            Some(pk) => pk,
+            None => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Malformed sender address".into(),
+                })
+            }
+        };
+        let sig = match from_base64(self.signature.as_ref().unwrap())
+            .map(|sig| Signature::from_slice(sig.as_slice()))
+        {
+            Ok(Ok(sig)) => sig,
+            _ => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Malformed signature".into(),
+                })
+            }
+        };
 
-        if let Ok(_) = pk.verify(self.calculate_hash(), &sig) {
+        if let Ok(_) = pk.verify(self.signing_message(), &sig) {
             Ok(true)
         } else {
             Ok(false)
@@ -96,7 +289,21 @@ pub struct Block {
     pub previous: Option<String>,
     pub timestamp: i64,
     pub transactions: Box<Vec<Transaction>>,
+    #[serde(default)]
+    pub merkle_root: String,
+    /// Only set on genesis blocks: the election the chain belongs to.
+    #[serde(default)]
+    pub genesis: Option<GenesisMetadata>,
+    /// Only set on mix blocks, which carry no transactions.
+    #[serde(default)]
+    pub mix: Option<MixStage>,
+    /// Which encoding `calculate_hash` uses, see `encoding`.
+    #[serde(default)]
+    pub version: u8,
     pub hash: Option<String>,
+    /// The election authority's signature over `hash`.
+    #[serde(default)]
+    pub signature: Option<String>,
 }
 impl Block {
     pub fn new(
@@ -104,69 +311,254 @@ impl Block {
         timestamp: i64,
         transactions: Box<Vec<Transaction>>,
     ) -> Self {
+        let merkle_root = merkle_root(&Block::transaction_hashes(&transactions));
         let mut block = Block {
             previous,
             timestamp,
             transactions,
+            merkle_root,
+            genesis: None,
+            mix: None,
+            version: CANONICAL_VERSION,
             hash: None,
+            signature: None,
         };
         let hash = block.calculate_hash();
         block.hash = Some(hash);
         block
     }
 
-    pub fn calculate_hash(&self) -> String {
-        let hash_input = format!(
-            "{}{}{}",
-            self.previous.as_ref().unwrap_or(&"".to_string()),
-            self.timestamp,
-            serde_json::to_string(&self.transactions).unwrap()
+    /// Builds a genesis block that commits to the election it was started for.
+    pub fn new_genesis(metadata: GenesisMetadata, transactions: Box<Vec<Transaction>>) -> Self {
+        let mut block = Block::new(Some(VOID_HASH.into()), metadata.started_at, transactions);
+        block.genesis = Some(metadata);
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    /// Builds a block recording one stage of the post-election mix.
+    pub fn new_mix(previous: Option<String>, timestamp: i64, stage: MixStage) -> Self {
+        let mut block = Block::new(previous, timestamp, Box::default());
+        block.mix = Some(stage);
+        block.hash = Some(block.calculate_hash());
+        block
+    }
+
+    /// The encoding hashed from version 1 on; legacy blocks hash display strings.
+    pub fn canonical_encoding(&self) -> Vec<u8> {
+        debug_assert!(
+            self.version >= CANONICAL_V1,
+            "legacy blocks have no canonical encoding"
         );
+        let mut encoder = CanonicalEncoder::new(BLOCK_DOMAIN, self.version);
+        encoder
+            .optional_string(self.previous.as_deref())
+            .i64(self.timestamp)
+            .string(&self.merkle_root)
+            .presence(self.genesis.is_some());
+        if let Some(genesis) = &self.genesis {
+            encoder
+                .string(&genesis.election_id)
+                .string(&genesis.title)
+                .strings(&genesis.candidates)
+                .string(&genesis.scope)
+                .string(&genesis.scope_value)
+                .i64(genesis.started_at)
+                .string(&genesis.authority);
+            if self.version >= CANONICAL_V2 {
+                genesis.voting_mode.encode(&mut encoder);
+            }
+            if self.version >= CANONICAL_V3 {
+                encoder.optional_string(genesis.encryption_key.as_deref());
+            }
+            if self.version >= CANONICAL_V5 {
+                encoder.optional_string(genesis.credential_key.as_deref());
+            }
+        }
+        if self.version >= CANONICAL_V4 {
+            encoder.presence(self.mix.is_some());
+            if let Some(mix) = &self.mix {
+                mix.encode(&mut encoder);
+            }
+        }
+        encoder.finish()
+    }
+
+    fn digest(&self) -> Vec<u8> {
         let mut hash = Sha256::new();
-        hash.update(hash_input);
-        format!("{:X}", hash.finalize())
+        if self.version == LEGACY_VERSION {
+            hash.update(format!(
+                "{}{}{}{}",
+                self.previous.as_ref().unwrap_or(&"".to_string()),
+                self.timestamp,
+                self.merkle_root,
+                match &self.genesis {
+                    Some(genesis) => serde_json::to_string(genesis).unwrap(),
+                    None => "".into(),
+                }
+            ));
+        } else {
+            hash.update(self.canonical_encoding());
+        }
+        hash.finalize().to_vec()
     }
 
-    pub fn is_valid(&self) -> bool {
-        for transaction in self.transactions.iter() {
-            if let Ok(false) = transaction.is_valid() {
-                return false;
-            }
+    pub fn calculate_hash(&self) -> String {
+        hex::encode_upper(self.digest())
+    }
+
+    /// What the authority signs: the raw digest, or its hex string for legacy blocks.
+    pub fn signing_message(&self) -> Vec<u8> {
+        if self.version == LEGACY_VERSION {
+            self.calculate_hash().into_bytes()
+        } else {
+            self.digest()
+        }
+    }
+
+    pub fn sign(&mut self, authority: &KeyPair) {
+        let sig = authority.sk.sign(self.signing_message(), None);
+        self.signature = Some(to_base64(&sig.to_vec()));
+    }
+
+    /// Whether the header signature was made by `authority` over this block's hash.
+    pub fn has_valid_signature(&self, authority: &PublicKey) -> bool {
+        let sig = match self.signature.as_ref().map(from_base64) {
+            Some(Ok(sig)) => Signature::from_slice(sig.as_slice()),
+            _ => return false,
+        };
+        match sig {
+            Ok(sig) => authority.verify(self.signing_message(), &sig).is_ok(),
+            Err(_) => false,
         }
+    }
+
+    pub fn transaction_hashes(transactions: &[Transaction]) -> Vec<String> {
+        transactions.iter().map(|t| t.calculate_hash()).collect()
+    }
+
+    /// Builds a proof that the transaction with `transaction_hash` is committed
+    /// to by this block's merkle root, or `None` if the block does not contain it.
+    pub fn inclusion_proof(&self, transaction_hash: &str) -> Option<Vec<MerkleProofStep>> {
+        let leaves = Block::transaction_hashes(&self.transactions);
+        let index = leaves.iter().position(|h| h == transaction_hash)?;
+        merkle_proof(&leaves, index)
+    }
+
+    /// Blocks stored before merkle roots were added deserialize with an empty root.
+    pub fn predates_merkle_roots(&self) -> bool {
+        self.version == LEGACY_VERSION && self.merkle_root.is_empty()
+    }
+
+    pub fn has_valid_merkle_root(&self) -> bool {
+        self.merkle_root == merkle_root(&Block::transaction_hashes(&self.transactions))
+    }
 
-        true
+    /// Hashes of the transactions in this block whose signatures do not check out.
+    pub fn invalid_transactions(&self) -> Vec<String> {
+        self.transactions
+            .iter()
+            .filter(|t| !matches!(t.is_valid(), Ok(true)))
+            .map(|t| t.calculate_hash())
+            .collect()
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct BlockChain {
+    #[serde(skip)]
+    chain_id: String,
     chain: Vec<Block>,
     transaction_buffer: Vec<Transaction>,
+    #[serde(skip)]
+    ledger: Ledger,
+    #[serde(skip)]
+    sealing_policy: SealingPolicy,
+    /// The election's status when the chain was loaded, which decides which
+    /// phase of a commit-reveal election new transactions may belong to.
+    #[serde(skip)]
+    status: Option<String>,
+    // how much of the chain and buffer the block store already holds
+    #[serde(skip)]
+    stored_height: usize,
+    #[serde(skip)]
+    stored_pending: usize,
 }
 
 impl BlockChain {
-    pub fn new(registered_addresses: Box<Vec<String>>) -> Self {
+    pub fn new(genesis: GenesisMetadata, registered_addresses: Box<Vec<String>>) -> Self {
         let mut block_chain = BlockChain {
+            chain_id: genesis.election_id.clone(),
             chain: vec![],
             transaction_buffer: vec![],
+            ledger: Ledger::default(),
+            sealing_policy: SealingPolicy::default(),
+            status: None,
+            stored_height: 0,
+            stored_pending: 0,
         };
-        let genesis_block = block_chain.create_genesis_block(registered_addresses);
+        let authority = authority_key_for(&genesis.authority);
+        let mut genesis_block = block_chain.create_genesis_block(genesis, registered_addresses);
+        genesis_block.sign(authority);
+        block_chain.ledger.apply_block(&genesis_block);
         block_chain.chain.push(genesis_block);
         block_chain
     }
 
-    pub fn create_genesis_block(&self, registered_addresses: Box<Vec<String>>) -> Block {
+    pub fn create_genesis_block(
+        &self,
+        genesis: GenesisMetadata,
+        registered_addresses: Box<Vec<String>>,
+    ) -> Block {
+        self.build_genesis_block(CANONICAL_VERSION, genesis, &registered_addresses)
+    }
+
+    /// Builds genesis in a given encoding version, so chains started before the
+    /// current encoding can still be checked against their election.
+    fn build_genesis_block(
+        &self,
+        version: u8,
+        genesis: GenesisMetadata,
+        registered_addresses: &[String],
+    ) -> Block {
         let mut genesis_transactions: Box<Vec<Transaction>> = Box::new(vec![]);
+        let allowance = genesis.voting_mode.allowance(genesis.candidates.len());
         for address in registered_addresses.iter() {
-            genesis_transactions.push(Transaction::new(VOID_ADDRESS.into(), address.clone(), 1))
+            // minted at the genesis timestamp so the block can be rebuilt from the roll
+            let mut transaction = Transaction::new(
+                self.chain_id.clone(),
+                VOID_ADDRESS.into(),
+                address.clone(),
+                allowance,
+                0,
+            );
+            transaction.timestamp = genesis.started_at;
+            transaction.version = version;
+            genesis_transactions.push(transaction)
         }
-        Block::new(Some(VOID_HASH.into()), 0, genesis_transactions)
+        let mut block = Block::new_genesis(genesis, genesis_transactions);
+        block.version = version;
+        block.merkle_root = merkle_root(&Block::transaction_hashes(&block.transactions));
+        block.hash = Some(block.calculate_hash());
+        block
     }
     pub fn get_latest_block(&self) -> &Block {
         &self.chain[self.chain.len() - 1]
     }
 
     pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), BlockchainError> {
+        if transaction.chain_id != self.chain_id {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Transaction belongs to another chain".into(),
+            });
+        }
+        // older versions are only read back from stored blocks, never accepted anew
+        if transaction.version != CANONICAL_VERSION {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: New transactions must use the current encoding"
+                    .into(),
+            });
+        }
         if Some(&transaction.from) == Some(&VOID_ADDRESS.into())
             || Some(&transaction.to) == Some(&VOID_ADDRESS.into())
         {
@@ -174,11 +566,34 @@ impl BlockChain {
                 content: "Invalid Transaction:: Invalid to or from address".into(),
             });
         }
-        if !transaction.is_valid().unwrap() {
+        if !self.mix_stages().is_empty() {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ballots have already been mixed".into(),
+            });
+        }
+        self.check_ballot(&transaction)?;
+        self.check_phase(&transaction)?;
+        self.check_candidate_cap(
+            &transaction,
+            self.ledger.sent(&transaction.from, &transaction.to),
+        )?;
+        self.check_ring_signature(&transaction)?;
+        self.check_credential(&transaction)?;
+        if !transaction.is_valid()? {
             return Err(BlockchainError::TransactionError {
                 content: "Invalid Transaction:: Transaction not valid".into(),
             });
         }
+        if transaction.is_anonymous() {
+            // a key image or ballot key holds no tokens, and may only ever send once
+            if self.ledger.next_nonce(&transaction.from) > 0 {
+                return Err(BlockchainError::NonceError {
+                    content: "Invalid Transaction:: This anonymous voter has already voted".into(),
+                });
+            }
+            self.accept_transaction(transaction);
+            return Ok(());
+        }
         if transaction.amount <= 0 {
             return Err(BlockchainError::TransactionError {
                 content: "Invalid Transaction:: Transaction amount should be greater than zero"
@@ -193,96 +608,722 @@ impl BlockChain {
             });
         }
 
-        if self.transaction_buffer.len() > 0 {
-            let pending_wallet_transactions: Vec<&Transaction> = self
-                .transaction_buffer
-                .iter()
-                .filter(|t| t.from == transaction.from)
-                .collect();
+        let expected_nonce = self.ledger.next_nonce(&transaction.from);
+        if transaction.nonce < expected_nonce {
+            return Err(BlockchainError::NonceError {
+                content: format!(
+                    "Invalid Transaction:: Nonce {} was already used, expected {}",
+                    transaction.nonce, expected_nonce
+                ),
+            });
+        }
+        if transaction.nonce > expected_nonce {
+            return Err(BlockchainError::NonceError {
+                content: format!(
+                    "Invalid Transaction:: Nonce {} is out of order, expected {}",
+                    transaction.nonce, expected_nonce
+                ),
+            });
+        }
 
-            let total_pending_amount = pending_wallet_transactions
-                .iter()
-                .map(|t| t.amount)
-                .reduce(|p, c| p + c)
-                .unwrap();
-            if total_pending_amount > wallet_balance {
-                return Err(BlockchainError::TransactionError {
-                    content: "Invalid Transaction:: Pending transaction amount exceeded".into(),
-                });
-            }
+        if self.ledger.pending_amount(&transaction.from) + transaction.amount > wallet_balance {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Pending transaction amount exceeded".into(),
+            });
         }
 
-        if self.transaction_buffer.len() < 10 {
-            self.transaction_buffer.push(transaction);
-        } else {
+        self.accept_transaction(transaction);
+        Ok(())
+    }
+
+    /// Buffers a checked transaction, sealing as the policy asks.
+    fn accept_transaction(&mut self, transaction: Transaction) {
+        if self.is_seal_due(Utc::now().timestamp()) {
             self.create_block_from_buffer();
-            self.transaction_buffer.clear();
-            self.transaction_buffer.push(transaction);
         }
-        Ok(())
+        self.ledger.add_pending(&transaction);
+        self.transaction_buffer.push(transaction);
+        if let SealingPolicy::MaxTransactions { count } = self.sealing_policy {
+            if self.transaction_buffer.len() >= count {
+                self.create_block_from_buffer();
+            }
+        }
     }
 
-    pub fn get_address_balance(&self, address: &String) -> i64 {
-        let mut balance = 0;
-        for block in &self.chain {
-            for trans in block.transactions.as_ref().iter() {
-                if trans.from == *address {
-                    balance -= trans.amount
+    /// The genesis metadata this chain was started with, if any.
+    pub fn genesis_metadata(&self) -> Option<&GenesisMetadata> {
+        self.chain.first().and_then(|genesis| genesis.genesis.as_ref())
+    }
+
+    /// The key that signs this chain's blocks: the one its genesis names.
+    fn signing_key(&self) -> &'static KeyPair {
+        authority_key_for(
+            self.genesis_metadata()
+                .map(|genesis| genesis.authority.as_str())
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn voting_mode(&self) -> VotingMode {
+        self.genesis_metadata()
+            .map(|genesis| genesis.voting_mode.clone())
+            .unwrap_or_default()
+    }
+
+    /// Checks that a transaction carries the kind of ballot the election's voting
+    /// mode expects, and that the ballot only names the election's candidates.
+    pub fn check_ballot(&self, transaction: &Transaction) -> Result<(), BlockchainError> {
+        let candidates = match self.genesis_metadata() {
+            Some(genesis) => &genesis.candidates,
+            None => return Ok(()),
+        };
+        if transaction.ballot.is_some()
+            && (transaction.to != BALLOT_ADDRESS || transaction.amount != 1)
+        {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ballots spend one token to the ballot box".into(),
+            });
+        }
+        let mode = self.voting_mode();
+        match (&transaction.ballot, &mode) {
+            (None, mode) if !mode.takes_ballot() => Ok(()),
+            (Some(Ballot::Ranked { preferences }), mode) if mode.is_ranked() => {
+                if preferences.is_empty() {
+                    return Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Ballot ranks no candidates".into(),
+                    });
+                }
+                for (index, preference) in preferences.iter().enumerate() {
+                    if !candidates.contains(preference) {
+                        return Err(BlockchainError::TransactionError {
+                            content: "Invalid Transaction:: Ballot ranks an unknown candidate"
+                                .into(),
+                        });
+                    }
+                    if preferences[..index].contains(preference) {
+                        return Err(BlockchainError::TransactionError {
+                            content: "Invalid Transaction:: Ballot ranks a candidate twice".into(),
+                        });
+                    }
+                }
+                Ok(())
+            }
+            (Some(Ballot::Encrypted { ciphertexts, proof }), VotingMode::Encrypted { .. }) => {
+                if ciphertexts.len() != candidates.len() {
+                    return Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Ballot needs one ciphertext per candidate"
+                            .into(),
+                    });
+                }
+                let election_key = self
+                    .genesis_metadata()
+                    .and_then(|genesis| genesis.encryption_key.as_deref())
+                    .and_then(point_from_hex);
+                let context = BallotContext {
+                    election_id: &self.chain_id,
+                    voter: &transaction.from,
+                };
+                match election_key {
+                    Some(key) if proof.verify(&context, &key, ciphertexts, ENCRYPTED_SELECTIONS) => Ok(()),
+                    Some(_) => Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Ballot validity proof does not verify"
+                            .into(),
+                    }),
+                    None => Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Election has no encryption key".into(),
+                    }),
+                }
+            }
+            // the nonce orders the phases: one commitment, then one reveal
+            (Some(Ballot::Commitment { commitment }), VotingMode::CommitReveal) => {
+                if transaction.nonce != 0 {
+                    return Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Voter has already committed".into(),
+                    });
+                }
+                if commitment.len() != 64 || hex::decode(commitment).is_err() {
+                    return Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Commitment is not a hex SHA-256 hash"
+                            .into(),
+                    });
+                }
+                Ok(())
+            }
+            (Some(Ballot::Reveal { .. }), VotingMode::CommitReveal) => {
+                if transaction.nonce != 1 {
+                    return Err(BlockchainError::TransactionError {
+                        content: "Invalid Transaction:: Reveals follow a single commitment".into(),
+                    });
                 }
-                if trans.to == *address {
-                    balance += trans.amount
+                Ok(())
+            }
+            _ => Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ballot does not match the election's voting mode"
+                    .into(),
+            }),
+        }
+    }
+
+    /// Checks that a new commitment arrives while the election is ongoing, and a
+    /// new reveal once it has moved to its reveal phase. Chains loaded without an
+    /// election status are not checked.
+    pub fn check_phase(&self, transaction: &Transaction) -> Result<(), BlockchainError> {
+        let status = match &self.status {
+            Some(status) => status.as_str(),
+            None => return Ok(()),
+        };
+        match (&transaction.ballot, status) {
+            (Some(Ballot::Commitment { .. }), status) if status != "ONGOING" => {
+                Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Commitments close when voting ends".into(),
+                })
+            }
+            (Some(Ballot::Reveal { .. }), status) if status != "REVEAL" => {
+                Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Reveals are only taken in the reveal phase".into(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Candidates chosen by reveals in sealed blocks that open their sender's
+    /// commitment. Reveals that do not match, or name no candidate, are not counted.
+    pub fn revealed_choices(&self) -> Vec<&String> {
+        let candidates = match self.genesis_metadata() {
+            Some(genesis) => &genesis.candidates,
+            None => return vec![],
+        };
+        let transactions = self.chain.iter().flat_map(|block| block.transactions.iter());
+        let commitments: HashMap<&String, &String> = transactions
+            .clone()
+            .filter_map(|transaction| match &transaction.ballot {
+                Some(Ballot::Commitment { commitment }) => Some((&transaction.from, commitment)),
+                _ => None,
+            })
+            .collect();
+        transactions
+            .filter_map(|transaction| match &transaction.ballot {
+                Some(Ballot::Reveal { candidate, salt }) => {
+                    let opened =
+                        vote_commitment(&self.chain_id, &transaction.from, candidate, salt);
+                    (candidates.contains(candidate)
+                        && commitments.get(&transaction.from) == Some(&&opened))
+                        .then_some(candidate)
                 }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The voters' keys genesis minted tokens to, which anonymous votes are ring signed over.
+    pub fn voter_roll(&self) -> Vec<&String> {
+        self.chain
+            .first()
+            .map(|genesis| genesis.transactions.iter().map(|t| &t.to).collect())
+            .unwrap_or_default()
+    }
+
+    /// The voter roll as ring members, in genesis order.
+    pub fn ring(&self) -> Vec<EdwardsPoint> {
+        self.voter_roll()
+            .into_iter()
+            .filter_map(pk_from_string)
+            .filter_map(|pk| edwards_point(&pk))
+            .collect()
+    }
+
+    /// Checks that anonymous elections only take ring signed votes, each a single
+    /// token to a candidate, sent from its key image and signed by someone on the roll.
+    pub fn check_ring_signature(&self, transaction: &Transaction) -> Result<(), BlockchainError> {
+        let genesis = match self.genesis_metadata() {
+            Some(genesis) => genesis,
+            None => return Ok(()),
+        };
+        let anonymous = genesis.voting_mode == VotingMode::Anonymous;
+        let ring_signature = match (&transaction.ring_signature, anonymous) {
+            (None, false) => return Ok(()),
+            (Some(ring_signature), true) => ring_signature,
+            (None, true) => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Votes in an anonymous election must be ring signed"
+                        .into(),
+                })
+            }
+            (Some(_), false) => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Election does not take anonymous votes".into(),
+                })
+            }
+        };
+        if !genesis.candidates.contains(&transaction.to)
+            || transaction.amount != 1
+            || transaction.nonce != 0
+        {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Anonymous votes send one token to a candidate"
+                    .into(),
+            });
+        }
+        if transaction.from != point_to_hex(&ring_signature.key_image) {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Anonymous votes are sent from their key image"
+                    .into(),
+            });
+        }
+        if !ring_signature.verify(&transaction.signing_message(), &self.ring()) {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ring signature does not verify".into(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that blind credential elections only take votes carrying a
+    /// credential, each a single token to a candidate from the ballot key the
+    /// election authority signed.
+    pub fn check_credential(&self, transaction: &Transaction) -> Result<(), BlockchainError> {
+        let genesis = match self.genesis_metadata() {
+            Some(genesis) => genesis,
+            None => return Ok(()),
+        };
+        let credentialed = genesis.voting_mode == VotingMode::BlindCredential;
+        let credential = match (&transaction.credential, credentialed) {
+            (None, false) => return Ok(()),
+            (Some(credential), true) => credential,
+            (None, true) => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Votes in this election need a ballot credential"
+                        .into(),
+                })
+            }
+            (Some(_), false) => {
+                return Err(BlockchainError::TransactionError {
+                    content: "Invalid Transaction:: Election does not take ballot credentials".into(),
+                })
+            }
+        };
+        if !genesis.candidates.contains(&transaction.to)
+            || transaction.amount != 1
+            || transaction.nonce != 0
+        {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Credentialed votes send one token to a candidate"
+                    .into(),
+            });
+        }
+        let authority = genesis.credential_key.as_deref().and_then(edwards_from_hex);
+        let message = credential_message(&transaction.chain_id, &transaction.from);
+        match authority {
+            Some(authority) if credential.verify(&authority, &message) => Ok(()),
+            _ => Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Ballot credential is not signed by the election authority"
+                    .into(),
+            }),
+        }
+    }
+
+    /// Checks a vote against the per-candidate cap of approval and cumulative
+    /// elections, given what the sender has already sent that candidate.
+    pub fn check_candidate_cap(
+        &self,
+        transaction: &Transaction,
+        already_sent: i64,
+    ) -> Result<(), BlockchainError> {
+        let (candidates, cap) = match self.genesis_metadata() {
+            Some(genesis) => match genesis.voting_mode.candidate_cap() {
+                Some(cap) => (&genesis.candidates, cap),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        if !candidates.contains(&transaction.to) {
+            return Err(BlockchainError::TransactionError {
+                content: "Invalid Transaction:: Votes can only be sent to candidates".into(),
+            });
+        }
+        if already_sent + transaction.amount > cap {
+            return Err(BlockchainError::TransactionError {
+                content: format!(
+                    "Invalid Transaction:: At most {} can be given to each candidate",
+                    cap
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Number of voters behind a candidate's sealed total.
+    pub fn voters_for(&self, candidate: &str) -> usize {
+        self.ledger.senders_to(candidate)
+    }
+
+    /// Encrypted ballots in sealed blocks, in chain order.
+    pub fn encrypted_ballots(&self) -> Vec<&Vec<Ciphertext>> {
+        self.ballots()
+            .into_iter()
+            .filter_map(|ballot| match ballot {
+                Ballot::Encrypted { ciphertexts, .. } => Some(ciphertexts),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Mix stages recorded so far, in order.
+    pub fn mix_stages(&self) -> Vec<&MixStage> {
+        self.chain.iter().filter_map(|block| block.mix.as_ref()).collect()
+    }
+
+    /// The encrypted ballots to tally: the output of the last mix stage, or the
+    /// ballots as cast when the election has not been mixed.
+    pub fn tally_ballots(&self) -> Vec<&EncryptedBallot> {
+        match self.mix_stages().last() {
+            Some(stage) => stage.ballots.iter().collect(),
+            None => self.encrypted_ballots(),
+        }
+    }
+
+    fn election_key(&self) -> Option<RistrettoPoint> {
+        self.genesis_metadata()
+            .and_then(|genesis| genesis.encryption_key.as_deref())
+            .and_then(point_from_hex)
+    }
+
+    /// Seals what is pending and appends a block recording `stage`, once its
+    /// shuffle proof checks out against the ballots it claims to have shuffled.
+    pub fn add_mix_stage(&mut self, stage: MixStage) -> Result<(), BlockchainError> {
+        let election_key = match self.election_key() {
+            Some(key) => key,
+            None => {
+                return Err(BlockchainError::ValidationError {
+                    content: "Only encrypted elections can be mixed".into(),
+                })
             }
+        };
+        if stage.stage as usize != self.mix_stages().len() + 1 {
+            return Err(BlockchainError::ValidationError {
+                content: format!("Expected mix stage {}", self.mix_stages().len() + 1),
+            });
+        }
+        self.create_block_from_buffer();
+        let inputs: Vec<EncryptedBallot> = self.tally_ballots().into_iter().cloned().collect();
+        if !stage.proof.verify(&election_key, &inputs, &stage.ballots) {
+            return Err(BlockchainError::ValidationError {
+                content: "Shuffle proof does not verify against the current ballots".into(),
+            });
+        }
+        let mut block = Block::new_mix(
+            self.get_latest_block().hash.clone(),
+            Utc::now().timestamp(),
+            stage,
+        );
+        block.sign(self.signing_key());
+        self.chain.push(block);
+        Ok(())
+    }
+
+    /// Ballots in sealed blocks, in chain order.
+    pub fn ballots(&self) -> Vec<&Ballot> {
+        self.chain
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .filter_map(|transaction| transaction.ballot.as_ref())
+            .collect()
+    }
+
+    pub fn set_sealing_policy(&mut self, sealing_policy: SealingPolicy) {
+        self.sealing_policy = sealing_policy;
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+    }
+
+    /// Whether the pending transactions have outlived a `MaxAge` policy at `now`.
+    pub fn is_seal_due(&self, now: i64) -> bool {
+        match (&self.sealing_policy, self.transaction_buffer.first()) {
+            (SealingPolicy::MaxAge { seconds }, Some(oldest)) => oldest.timestamp + seconds <= now,
+            _ => false,
         }
-        balance
     }
 
+    pub fn chain_id(&self) -> &String {
+        &self.chain_id
+    }
+
+    /// Nonce the next transaction from `address` has to carry.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        self.ledger.next_nonce(address)
+    }
+
+    pub fn get_address_balance(&self, address: &str) -> i64 {
+        self.ledger.balance(address)
+    }
+
+    /// Balance left to spend once the address's pending transactions are sealed.
+    pub fn get_available_balance(&self, address: &str) -> i64 {
+        self.ledger.available_balance(address)
+    }
+
+    /// Replays the whole chain and reports whether the maintained ledger agrees with it.
+    pub fn ledger_matches_chain(&self) -> bool {
+        self.ledger == Ledger::from_chain(&self.chain, &self.transaction_buffer)
+    }
+
+    pub fn rebuild_ledger(&mut self) {
+        self.ledger = Ledger::from_chain(&self.chain, &self.transaction_buffer);
+    }
+
+    /// Seals the transaction buffer into a new block and empties it.
     pub fn create_block_from_buffer(&mut self) {
         let latest_block = &self.get_latest_block();
         let trx = &self.transaction_buffer;
         if trx.len() > 0 {
-            let block = Block::new(
+            let mut block = Block::new(
                 Some(latest_block.hash.clone().unwrap()),
                 Utc::now().timestamp(),
                 Box::new(trx.clone()),
             );
-            self.chain.push(block)
+            block.sign(self.signing_key());
+            self.ledger.apply_block(&block);
+            self.ledger.clear_pending();
+            self.chain.push(block);
+            self.transaction_buffer.clear();
         }
     }
 
-    pub fn is_valid(&self, registered_addresses: Box<Vec<String>>) -> bool {
-        let real_genesis = self.create_genesis_block(registered_addresses);
-        let current_genesis = self.chain.get(0);
+    /// Checks every block against its own contents and its predecessor, and the
+    /// genesis block against the election record and its voter roll.
+    pub fn validate(
+        &self,
+        genesis: GenesisMetadata,
+        registered_addresses: &[String],
+    ) -> ValidationReport {
+        let mut issues: Vec<ValidationIssue> = vec![];
+        let mut report = |height: usize, failure: ValidationFailure, message: String| {
+            issues.push(ValidationIssue {
+                height,
+                failure,
+                message,
+            })
+        };
 
-        if serde_json::to_string(&real_genesis).unwrap()
-            != serde_json::to_string(&current_genesis.unwrap()).unwrap()
-        {
-            println!("got here");
-            return false;
+        let version = self
+            .chain
+            .first()
+            .map(|block| block.version)
+            .unwrap_or(CANONICAL_VERSION);
+        let real_genesis = self.build_genesis_block(version, genesis, registered_addresses);
+        match self.chain.first() {
+            Some(genesis) => {
+                match (&genesis.genesis, &real_genesis.genesis) {
+                    (Some(metadata), Some(expected)) => {
+                        let mismatched = metadata.mismatched_fields(expected);
+                        if !mismatched.is_empty() {
+                            report(
+                                0,
+                                ValidationFailure::GenesisMismatch,
+                                format!(
+                                    "Genesis does not match the election's {}",
+                                    mismatched.join(", ")
+                                ),
+                            );
+                        }
+                    }
+                    _ => report(
+                        0,
+                        ValidationFailure::GenesisMismatch,
+                        "Genesis block does not commit to any election".into(),
+                    ),
+                }
+                if genesis.transactions != real_genesis.transactions {
+                    report(
+                        0,
+                        ValidationFailure::GenesisMismatch,
+                        "Genesis allocations do not match the election's voter roll".into(),
+                    );
+                }
+            }
+            None => report(
+                0,
+                ValidationFailure::GenesisMismatch,
+                "Chain has no genesis block".into(),
+            ),
         }
-        for i in 1..self.chain.len() {
-            let current_block = self.chain.get(i).unwrap();
-            let prev_block = self.chain.get(i - 1).unwrap();
 
-            if prev_block.hash != current_block.hash {
-                return false;
+        // blocks are checked against the authority genesis names; a forged genesis
+        // naming another key is already reported as a mismatch above
+        let authority = self
+            .genesis_metadata()
+            .and_then(|metadata| pk_from_string(&metadata.authority));
+
+        let mut next_nonces: HashMap<&String, u64> = HashMap::new();
+        let mut sent: HashMap<(&String, &String), i64> = HashMap::new();
+        // what the next mix stage has to have shuffled
+        let mut mix_input: Vec<EncryptedBallot> = vec![];
+        let mut mixed = 0;
+        let election_key = self.election_key();
+        for (height, block) in self.chain.iter().enumerate() {
+            if let Some(stage) = &block.mix {
+                let verified = match &election_key {
+                    Some(key) => stage.proof.verify(key, &mix_input, &stage.ballots),
+                    None => false,
+                };
+                mixed += 1;
+                if !verified || stage.stage != mixed || !block.transactions.is_empty() {
+                    report(
+                        height,
+                        ValidationFailure::BadShuffle,
+                        format!("Mix stage {} does not shuffle the ballots before it", stage.stage),
+                    );
+                }
+                mix_input = stage.ballots.clone();
             }
-            if !current_block.is_valid() {
-                return false;
+            match &authority {
+                Some(authority) if block.has_valid_signature(authority) => {}
+                _ => report(
+                    height,
+                    ValidationFailure::BadAuthoritySignature,
+                    "Block header is not signed by the election authority".into(),
+                ),
             }
-
-            if current_block.hash != Some(current_block.calculate_hash()) {
-                return false;
+            for transaction in block.transactions.iter() {
+                if transaction.chain_id != self.chain_id {
+                    report(
+                        height,
+                        ValidationFailure::ForeignTransaction,
+                        format!(
+                            "Transaction {} is bound to chain {:?}",
+                            transaction.calculate_hash(),
+                            transaction.chain_id
+                        ),
+                    );
+                }
+                if transaction.from == VOID_ADDRESS {
+                    continue;
+                }
+                if let Some(Ballot::Encrypted { ciphertexts, .. }) = &transaction.ballot {
+                    if mixed > 0 {
+                        report(
+                            height,
+                            ValidationFailure::InvalidBallot,
+                            "Ballot was cast after mixing began".into(),
+                        );
+                    }
+                    mix_input.push(ciphertexts.clone());
+                }
+                if let Err(e) = self.check_ballot(transaction) {
+                    report(height, ValidationFailure::InvalidBallot, e.to_string());
+                }
+                let already_sent = sent.entry((&transaction.from, &transaction.to)).or_insert(0);
+                if let Err(e) = self.check_candidate_cap(transaction, *already_sent) {
+                    report(height, ValidationFailure::InvalidBallot, e.to_string());
+                }
+                *already_sent += transaction.amount;
+                if let Err(e) = self.check_ring_signature(transaction) {
+                    report(height, ValidationFailure::BadSignature, e.to_string());
+                }
+                if let Err(e) = self.check_credential(transaction) {
+                    report(height, ValidationFailure::BadSignature, e.to_string());
+                }
+                let expected_nonce = next_nonces.entry(&transaction.from).or_insert(0);
+                if transaction.nonce != *expected_nonce {
+                    report(
+                        height,
+                        ValidationFailure::BadNonce,
+                        format!(
+                            "Transaction {} has nonce {}, expected {}",
+                            transaction.calculate_hash(),
+                            transaction.nonce,
+                            expected_nonce
+                        ),
+                    );
+                }
+                *expected_nonce = (*expected_nonce).max(transaction.nonce + 1);
+            }
+            if height > 0 {
+                let prev_block = &self.chain[height - 1];
+                if block.previous != prev_block.hash {
+                    report(
+                        height,
+                        ValidationFailure::BrokenLink,
+                        format!(
+                            "Block points at {:?} but the previous block hashes to {:?}",
+                            block.previous, prev_block.hash
+                        ),
+                    );
+                }
+                if block.timestamp < prev_block.timestamp {
+                    report(
+                        height,
+                        ValidationFailure::TimestampRegression,
+                        format!(
+                            "Block timestamp {} is earlier than the previous block's {}",
+                            block.timestamp, prev_block.timestamp
+                        ),
+                    );
+                }
+            }
+            if block.version > CANONICAL_VERSION {
+                report(
+                    height,
+                    ValidationFailure::BadHash,
+                    format!("Block uses unsupported encoding version {}", block.version),
+                );
+            } else if block.hash != Some(block.calculate_hash()) {
+                report(
+                    height,
+                    ValidationFailure::BadHash,
+                    "Stored block hash does not match its contents".into(),
+                );
+            }
+            if block.predates_merkle_roots() {
+                report(
+                    height,
+                    ValidationFailure::BadMerkleRoot,
+                    "Block was sealed before merkle roots and cannot be re-verified".into(),
+                );
+            } else if !block.has_valid_merkle_root() {
+                report(
+                    height,
+                    ValidationFailure::BadMerkleRoot,
+                    "Merkle root does not match the block's transactions".into(),
+                );
+            }
+            for transaction_hash in block.invalid_transactions() {
+                report(
+                    height,
+                    ValidationFailure::BadSignature,
+                    format!("Transaction {} has an invalid signature", transaction_hash),
+                );
             }
         }
-        true
-    }
 
-    pub fn serialize(&mut self) -> String {
-        if self.transaction_buffer.len() > 0 {
-            self.create_block_from_buffer();
-            self.transaction_buffer.clear();
+        ValidationReport {
+            valid: issues.is_empty(),
+            blocks_checked: self.chain.len(),
+            failed_height: issues.iter().map(|issue| issue.height).min(),
+            ledger_consistent: self.ledger_matches_chain(),
+            issues,
         }
+    }
+
+    /// Seals whatever is waiting in the transaction buffer into a new block.
+    pub fn seal_pending(&mut self) {
+        self.create_block_from_buffer();
+    }
+
+    pub fn blocks(&self) -> &Vec<Block> {
+        &self.chain
+    }
+
+    pub fn pending_transactions(&self) -> &Vec<Transaction> {
+        &self.transaction_buffer
+    }
+
+    /// Serializes the sealed blocks. Pending transactions are left out and stay
+    /// in the buffer; sealing is up to the chain's `SealingPolicy`.
+    pub fn serialize(&self) -> String {
         serde_json::to_string(&self.chain).unwrap()
     }
 
@@ -290,12 +1331,104 @@ impl BlockChain {
         let chain: Result<Vec<Block>, _> = serde_json::from_str(json.as_str());
         if let Ok(chain) = chain {
             self.chain = chain;
+            self.rebuild_ledger();
         }
     }
 
-    pub fn from_string(s: String) -> Self {
-        let mut bc = BlockChain::new(Box::new(vec![]));
+    /// Looks up a sealed transaction by hash, returning the height of the block
+    /// that contains it.
+    pub fn find_transaction(&self, transaction_hash: &str) -> Option<(usize, &Block)> {
+        self.chain.iter().enumerate().find(|(_, block)| {
+            block
+                .transactions
+                .iter()
+                .any(|t| t.calculate_hash() == *transaction_hash)
+        })
+    }
+
+    /// Reassembles a chain from blocks and pending transactions loaded from the block store.
+    pub fn from_parts(
+        chain_id: String,
+        chain: Vec<Block>,
+        transaction_buffer: Vec<Transaction>,
+    ) -> Self {
+        BlockChain {
+            chain_id,
+            ledger: Ledger::from_chain(&chain, &transaction_buffer),
+            sealing_policy: SealingPolicy::default(),
+            status: None,
+            stored_height: chain.len(),
+            stored_pending: transaction_buffer.len(),
+            chain,
+            transaction_buffer,
+        }
+    }
+
+    /// Returns the number of blocks and pending transactions held by the block store.
+    pub fn stored(&self) -> (usize, usize) {
+        (self.stored_height, self.stored_pending)
+    }
+
+    pub fn mark_stored(&mut self) {
+        self.stored_height = self.chain.len();
+        self.stored_pending = self.transaction_buffer.len();
+    }
+
+    pub fn from_string(chain_id: String, s: String) -> Self {
+        let mut bc = BlockChain::from_parts(chain_id, vec![], vec![]);
         bc.deserialize(s);
         bc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(status: &str) -> BlockChain {
+        let mut blockchain = BlockChain::from_parts("chain".into(), vec![], vec![]);
+        blockchain.set_status(status.into());
+        blockchain
+    }
+
+    fn commitment() -> Transaction {
+        let ballot = Ballot::Commitment {
+            commitment: "00".repeat(32),
+        };
+        Transaction::new_ballot("chain".into(), "voter".into(), ballot, 0)
+    }
+
+    fn reveal() -> Transaction {
+        let ballot = Ballot::Reveal {
+            candidate: "alice".into(),
+            salt: "salt".into(),
+        };
+        Transaction::new_ballot("chain".into(), "voter".into(), ballot, 1)
+    }
+
+    #[test]
+    fn commitments_only_while_ongoing() {
+        assert!(chain("ONGOING").check_phase(&commitment()).is_ok());
+        assert!(chain("REVEAL").check_phase(&commitment()).is_err());
+        assert!(chain("ENDED").check_phase(&commitment()).is_err());
+    }
+
+    #[test]
+    fn reveals_only_in_the_reveal_phase() {
+        assert!(chain("ONGOING").check_phase(&reveal()).is_err());
+        assert!(chain("REVEAL").check_phase(&reveal()).is_ok());
+        assert!(chain("ENDED").check_phase(&reveal()).is_err());
+    }
+
+    #[test]
+    fn new_transactions_use_the_current_encoding() {
+        let mut older = commitment();
+        older.version = CANONICAL_V4;
+        match chain("ONGOING").add_transaction(older) {
+            Err(BlockchainError::TransactionError { content }) => {
+                assert!(content.contains("current encoding"))
+            }
+            _ => panic!("an older encoding was accepted"),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use super::{Block, Transaction};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlockRecord {
+    pub election_id: ObjectId,
+    pub height: i64,
+    pub block: Block,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PendingTransactionRecord {
+    pub election_id: ObjectId,
+    pub position: i64,
+    pub transaction: Transaction,
+}
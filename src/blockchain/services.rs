@@ -0,0 +1,378 @@
+use actix_web::web;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::{Acknowledgment, FindOptions, ReadConcern, TransactionOptions, WriteConcern},
+    Client, ClientSession, Collection,
+};
+use serde_json::json;
+
+use crate::{election::models::Election, DB_NAME};
+
+use super::{
+    models::{BlockRecord, PendingTransactionRecord},
+    types::{BlockchainError, InclusionProofDTO},
+    utils::verify_merkle_proof,
+    BlockChain,
+};
+
+const ELECTION_COLLECTION: &str = "election";
+pub const BLOCK_COLLECTION: &str = "blocks";
+pub const PENDING_TRANSACTION_COLLECTION: &str = "pending_transactions";
+
+pub async fn start_session(client: &Client) -> Result<ClientSession, BlockchainError> {
+    client
+        .start_session(None)
+        .await
+        .map_err(|e| BlockchainError::InternalError {
+            content: e.to_string(),
+        })
+}
+
+/// Starts a session with a transaction open, so new blocks, pending
+/// transactions and the election document are written together or not at all.
+pub async fn start_transaction(client: &Client) -> Result<ClientSession, BlockchainError> {
+    let mut session = start_session(client).await?;
+    let options = TransactionOptions::builder()
+        .read_concern(ReadConcern::majority())
+        .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
+        .build();
+    session
+        .start_transaction(options)
+        .await
+        .map_err(|e| BlockchainError::InternalError {
+            content: e.to_string(),
+        })?;
+    Ok(session)
+}
+
+/// Loads an election's chain from the block store. Elections started before the
+/// block store existed keep their chain serialized on the election document; those
+/// are read from there until their next save moves them over.
+pub async fn load_blockchain(
+    session: &mut ClientSession,
+    election_id: &ObjectId,
+    election: &Election,
+) -> Result<BlockChain, BlockchainError> {
+    let block_collection: Collection<BlockRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(BLOCK_COLLECTION);
+    let pending_collection: Collection<PendingTransactionRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(PENDING_TRANSACTION_COLLECTION);
+
+    let options = FindOptions::builder().sort(doc! {"height": 1}).build();
+    let mut cursor = block_collection
+        .find_with_session(doc! {"election_id": election_id}, options, session)
+        .await
+        .map_err(|e| BlockchainError::InternalError {
+            content: e.to_string(),
+        })?;
+    let mut blocks = vec![];
+    while let Some(record) = cursor.next(session).await {
+        match record {
+            Ok(record) => blocks.push(record.block),
+            Err(e) => {
+                return Err(BlockchainError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        }
+    }
+
+    if blocks.is_empty() {
+        return match &election.blockchain {
+            Some(blockchain) => {
+                let mut blockchain = BlockChain::from_string(election_id.to_hex(), blockchain.clone());
+                blockchain.set_sealing_policy(election.sealing_policy.clone());
+                blockchain.set_status(election.status.clone());
+                Ok(blockchain)
+            }
+            None => Err(BlockchainError::ValidationError {
+                content: "Election has not started".into(),
+            }),
+        };
+    }
+
+    let options = FindOptions::builder().sort(doc! {"position": 1}).build();
+    let mut cursor = pending_collection
+        .find_with_session(doc! {"election_id": election_id}, options, session)
+        .await
+        .map_err(|e| BlockchainError::InternalError {
+            content: e.to_string(),
+        })?;
+    let mut pending = vec![];
+    while let Some(record) = cursor.next(session).await {
+        match record {
+            Ok(record) => pending.push(record.transaction),
+            Err(e) => {
+                return Err(BlockchainError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        }
+    }
+
+    let mut blockchain = BlockChain::from_parts(election_id.to_hex(), blocks, pending);
+    blockchain.set_sealing_policy(election.sealing_policy.clone());
+    blockchain.set_status(election.status.clone());
+    Ok(blockchain)
+}
+
+/// Writes what changed since the chain was loaded: new blocks are appended, and
+/// the pending transactions are either appended to or, when a block was sealed
+/// from them, replaced. Run it in a transaction from `start_transaction`, or a
+/// failure part way leaves the block store half written.
+pub async fn save_blockchain(
+    session: &mut ClientSession,
+    election_id: &ObjectId,
+    blockchain: &mut BlockChain,
+) -> Result<(), BlockchainError> {
+    let block_collection: Collection<BlockRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(BLOCK_COLLECTION);
+    let pending_collection: Collection<PendingTransactionRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(PENDING_TRANSACTION_COLLECTION);
+
+    let (stored_height, stored_pending) = blockchain.stored();
+    let blocks = blockchain.blocks();
+    let pending = blockchain.pending_transactions();
+    let mut first_pending = stored_pending;
+
+    if blocks.len() > stored_height {
+        let records: Vec<BlockRecord> = blocks[stored_height..]
+            .iter()
+            .enumerate()
+            .map(|(offset, block)| BlockRecord {
+                election_id: *election_id,
+                height: (stored_height + offset) as i64,
+                block: block.clone(),
+            })
+            .collect();
+        block_collection
+            .insert_many_with_session(records, None, session)
+            .await
+            .map_err(|e| BlockchainError::InternalError {
+                content: e.to_string(),
+            })?;
+
+        // a chain migrated from the election document drops its legacy copy
+        // in the same transaction, so the two never disagree
+        if stored_height == 0 {
+            let election_collection: Collection<Document> = session
+                .client()
+                .database(DB_NAME)
+                .collection(ELECTION_COLLECTION);
+            election_collection
+                .update_one_with_session(
+                    doc! {"_id": election_id},
+                    doc! {"$unset": {"blockchain": ""}},
+                    None,
+                    session,
+                )
+                .await
+                .map_err(|e| BlockchainError::InternalError {
+                    content: e.to_string(),
+                })?;
+        }
+
+        if stored_pending > 0 {
+            pending_collection
+                .delete_many_with_session(doc! {"election_id": election_id}, None, session)
+                .await
+                .map_err(|e| BlockchainError::InternalError {
+                    content: e.to_string(),
+                })?;
+        }
+        first_pending = 0;
+    }
+
+    if pending.len() > first_pending {
+        let records: Vec<PendingTransactionRecord> = pending[first_pending..]
+            .iter()
+            .enumerate()
+            .map(|(offset, transaction)| PendingTransactionRecord {
+                election_id: *election_id,
+                position: (first_pending + offset) as i64,
+                transaction: transaction.clone(),
+            })
+            .collect();
+        pending_collection
+            .insert_many_with_session(records, None, session)
+            .await
+            .map_err(|e| BlockchainError::InternalError {
+                content: e.to_string(),
+            })?;
+    }
+    blockchain.mark_stored();
+    Ok(())
+}
+
+pub async fn get_inclusion_proof(
+    client: web::Data<Client>,
+    election_id: String,
+    transaction_hash: String,
+) -> Result<String, BlockchainError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let mut session = start_session(&client).await?;
+
+    let id = match ObjectId::parse_str(&election_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Err(BlockchainError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = election_collection.find_one(doc! {"_id": id}, None).await;
+
+    match election {
+        Ok(Some(election)) => {
+            let blockchain = load_blockchain(&mut session, &id, &election).await?;
+            let transaction_hash = transaction_hash.to_uppercase();
+
+            if let Some((height, block)) = blockchain.find_transaction(&transaction_hash) {
+                let proof = block.inclusion_proof(&transaction_hash).unwrap();
+                if !verify_merkle_proof(&transaction_hash, &proof, &block.merkle_root) {
+                    return Err(BlockchainError::InternalError {
+                        content: "Block merkle root does not match its transactions".into(),
+                    });
+                }
+
+                let value = json!(InclusionProofDTO {
+                    election_id,
+                    transaction_hash,
+                    block_height: height,
+                    block_hash: block.hash.clone().unwrap(),
+                    merkle_root: block.merkle_root.clone(),
+                    proof,
+                })
+                .to_string();
+                return Ok(value);
+            }
+            Err(BlockchainError::ValidationError {
+                content: "Transaction not found in any sealed block".into(),
+            })
+        }
+        Ok(None) => Err(BlockchainError::ValidationError {
+            content: "Invalid election id".into(),
+        }),
+        Err(e) => Err(BlockchainError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+pub async fn seal_pending_transactions(
+    client: web::Data<Client>,
+    election_id: String,
+) -> Result<String, BlockchainError> {
+    let mut session = start_transaction(&client).await?;
+    let election_collection: Collection<Election> = session
+        .client()
+        .database(DB_NAME)
+        .collection(ELECTION_COLLECTION);
+
+    let id = match ObjectId::parse_str(&election_id) {
+        Ok(id) => id,
+        Err(_) => {
+            let _ = session.abort_transaction().await;
+            return Err(BlockchainError::ValidationError {
+                content: "Invalid election id".into(),
+            });
+        }
+    };
+    let election = election_collection
+        .find_one_with_session(doc! {"_id": id}, None, &mut session)
+        .await;
+
+    match election {
+        Ok(Some(election)) => {
+            let mut blockchain = match load_blockchain(&mut session, &id, &election).await {
+                Ok(blockchain) => blockchain,
+                Err(e) => {
+                    let _ = session.abort_transaction().await;
+                    return Err(e);
+                }
+            };
+            let sealed = blockchain.pending_transactions().len();
+            blockchain.seal_pending();
+            if let Err(e) = save_blockchain(&mut session, &id, &mut blockchain).await {
+                let _ = session.abort_transaction().await;
+                return Err(e);
+            }
+            session
+                .commit_transaction()
+                .await
+                .map_err(|e| BlockchainError::InternalError {
+                    content: e.to_string(),
+                })?;
+
+            let value = json!({
+                "message": "Pending transactions sealed",
+                "sealed_transactions": sealed,
+                "height": blockchain.blocks().len() - 1
+            })
+            .to_string();
+            Ok(value)
+        }
+        Ok(None) => {
+            let _ = session.abort_transaction().await;
+            Err(BlockchainError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            Err(BlockchainError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    }
+}
+
+pub async fn validate_blockchain(
+    client: web::Data<Client>,
+    election_id: String,
+) -> Result<String, BlockchainError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let mut session = start_session(&client).await?;
+
+    let id = match ObjectId::parse_str(&election_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Err(BlockchainError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = election_collection.find_one(doc! {"_id": id}, None).await;
+
+    match election {
+        Ok(Some(election)) => {
+            let genesis = match election.genesis_metadata(&id) {
+                Some(genesis) => genesis,
+                None => {
+                    return Err(BlockchainError::ValidationError {
+                        content: "Election has not started".into(),
+                    })
+                }
+            };
+            let blockchain = load_blockchain(&mut session, &id, &election).await?;
+            let report = blockchain.validate(genesis, &election.voters);
+            Ok(json!(report).to_string())
+        }
+        Ok(None) => Err(BlockchainError::ValidationError {
+            content: "Invalid election id".into(),
+        }),
+        Err(e) => Err(BlockchainError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
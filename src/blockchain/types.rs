@@ -6,6 +6,10 @@ use actix_web::{
 use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
 
+use crate::crypto::shuffle::{EncryptedBallot, ShuffleProof};
+
+use super::encoding::CanonicalEncoder;
+
 
 #[derive(Debug, Display, Error)]
 pub enum BlockchainError {
@@ -17,6 +21,9 @@ pub enum BlockchainError {
 
     #[display(fmt = "{}", content)]
     TransactionError { content: String },
+
+    #[display(fmt = "{}", content)]
+    NonceError { content: String },
 }
 
 impl error::ResponseError for BlockchainError {
@@ -30,7 +37,8 @@ impl error::ResponseError for BlockchainError {
         match *self {
             BlockchainError::ValidationError { .. } => StatusCode::BAD_REQUEST,
             BlockchainError::InternalError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
-            BlockchainError::TransactionError { .. } => StatusCode::NOT_ACCEPTABLE
+            BlockchainError::TransactionError { .. } => StatusCode::NOT_ACCEPTABLE,
+            BlockchainError::NonceError { .. } => StatusCode::CONFLICT,
         }
     }
 }
@@ -40,3 +48,250 @@ pub struct DTO {
     pub r: String,
     pub p: String,
 }
+
+/// What a genesis block commits to, so that a chain can only ever belong to the
+/// election it was started for.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GenesisMetadata {
+    pub election_id: String,
+    pub title: String,
+    pub candidates: Vec<String>,
+    pub scope: String,
+    pub scope_value: String,
+    pub started_at: i64,
+    /// Public key of the election authority that signs every block header.
+    #[serde(default)]
+    pub authority: String,
+    #[serde(default)]
+    pub voting_mode: VotingMode,
+    /// ElGamal key from the trustee ceremony that encrypted ballots are cast under.
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    /// Key ballot credentials are blind signed with, in elections that take them.
+    #[serde(default)]
+    pub credential_key: Option<String>,
+}
+
+impl GenesisMetadata {
+    /// Names of the fields that differ from `expected`.
+    pub fn mismatched_fields(&self, expected: &GenesisMetadata) -> Vec<&'static str> {
+        let mut fields = vec![];
+        if self.election_id != expected.election_id {
+            fields.push("election id");
+        }
+        if self.title != expected.title {
+            fields.push("title");
+        }
+        if self.candidates != expected.candidates {
+            fields.push("candidates");
+        }
+        if self.scope != expected.scope || self.scope_value != expected.scope_value {
+            fields.push("scope");
+        }
+        if self.started_at != expected.started_at {
+            fields.push("start time");
+        }
+        if self.authority != expected.authority {
+            fields.push("authority");
+        }
+        if self.voting_mode != expected.voting_mode {
+            fields.push("voting mode");
+        }
+        if self.encryption_key != expected.encryption_key {
+            fields.push("encryption key");
+        }
+        if self.credential_key != expected.credential_key {
+            fields.push("credential key");
+        }
+        fields
+    }
+}
+
+/// How voters express their choice, and so which transactions the chain accepts.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VotingMode {
+    /// A single token sent straight to one candidate.
+    #[default]
+    Plurality,
+    /// A ranked ballot over the candidates, counted by instant runoff.
+    RankedChoice,
+    /// Ranked ballots filling `seats` seats by single transferable vote, with a
+    /// Droop quota and fractional surplus transfers.
+    Stv { seats: u32 },
+    /// One token per candidate; voters send a token to each candidate they approve of.
+    Approval,
+    /// `points` tokens to spread across the candidates, at most `max_per_candidate` each.
+    Cumulative { points: u32, max_per_candidate: u32 },
+    /// A ballot encrypted under a key shared by the election's trustees, of
+    /// whom `threshold` are needed to decrypt the tally.
+    Encrypted { threshold: u32 },
+    /// A single token sent to one candidate from a key image, ring signed over
+    /// the voter roll so the chain can tell a voter has voted but not who they are.
+    Anonymous,
+    /// A single token sent to one candidate from a one-time ballot key, which the
+    /// election authority has blind signed for a voter on the roll.
+    BlindCredential,
+    /// Voters commit to a candidate while the election is ongoing and reveal it
+    /// once voting closes, so no partial result exists before then.
+    CommitReveal,
+}
+
+impl VotingMode {
+    /// Whether voters submit a ranked ballot rather than a single token.
+    pub fn is_ranked(&self) -> bool {
+        matches!(self, VotingMode::RankedChoice | VotingMode::Stv { .. })
+    }
+
+    /// Whether votes are ballots sent to the ballot box rather than tokens sent to candidates.
+    pub fn takes_ballot(&self) -> bool {
+        self.is_ranked() || matches!(self, VotingMode::Encrypted { .. } | VotingMode::CommitReveal)
+    }
+
+    /// Tokens genesis mints to each voter.
+    pub fn allowance(&self, candidates: usize) -> i64 {
+        match self {
+            VotingMode::Approval => candidates as i64,
+            VotingMode::Cumulative { points, .. } => *points as i64,
+            // one token to commit, one to reveal
+            VotingMode::CommitReveal => 2,
+            _ => 1,
+        }
+    }
+
+    /// Most a voter may send to any one candidate, for modes that spread an allowance.
+    pub fn candidate_cap(&self) -> Option<i64> {
+        match self {
+            VotingMode::Approval => Some(1),
+            VotingMode::Cumulative {
+                max_per_candidate, ..
+            } => Some(*max_per_candidate as i64),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        match self {
+            VotingMode::Plurality => encoder.string("PLURALITY"),
+            VotingMode::RankedChoice => encoder.string("RANKED_CHOICE"),
+            VotingMode::Stv { seats } => encoder.string("STV").u64(*seats as u64),
+            VotingMode::Approval => encoder.string("APPROVAL"),
+            VotingMode::Cumulative {
+                points,
+                max_per_candidate,
+            } => encoder
+                .string("CUMULATIVE")
+                .u64(*points as u64)
+                .u64(*max_per_candidate as u64),
+            VotingMode::Encrypted { threshold } => encoder.string("ENCRYPTED").u64(*threshold as u64),
+            VotingMode::Anonymous => encoder.string("ANONYMOUS"),
+            VotingMode::BlindCredential => encoder.string("BLIND_CREDENTIAL"),
+            VotingMode::CommitReveal => encoder.string("COMMIT_REVEAL"),
+        };
+    }
+}
+
+/// One trustee's re-encryption shuffle of an encrypted election's ballots, made
+/// after voting closes. Each stage shuffles the output of the one before it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MixStage {
+    /// Counting from 1.
+    pub stage: u32,
+    pub trustee: u32,
+    pub public_key: String,
+    pub ballots: Vec<EncryptedBallot>,
+    pub proof: ShuffleProof,
+}
+
+impl MixStage {
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .u64(self.stage as u64)
+            .u64(self.trustee as u64)
+            .string(&self.public_key)
+            .count(self.ballots.len());
+        for ballot in self.ballots.iter() {
+            encoder.count(ballot.len());
+            for ciphertext in ballot {
+                ciphertext.encode(encoder);
+            }
+        }
+        self.proof.encode(encoder);
+    }
+}
+
+/// Decides when pending transactions are sealed into a block. Chains are never
+/// sealed as a side effect of being saved, so the policy alone fixes the block layout.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SealingPolicy {
+    /// Seal as soon as `count` transactions are pending.
+    MaxTransactions { count: usize },
+    /// Seal before accepting a transaction once the oldest pending one is `seconds` old.
+    MaxAge { seconds: i64 },
+    /// Only seal when an admin asks for it, or when the election ends.
+    Manual,
+}
+
+impl Default for SealingPolicy {
+    fn default() -> Self {
+        SealingPolicy::MaxTransactions { count: 10 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValidationFailure {
+    GenesisMismatch,
+    BrokenLink,
+    BadHash,
+    BadMerkleRoot,
+    BadSignature,
+    BadAuthoritySignature,
+    BadNonce,
+    ForeignTransaction,
+    InvalidBallot,
+    TimestampRegression,
+    BadShuffle,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationIssue {
+    pub height: usize,
+    pub failure: ValidationFailure,
+    pub message: String,
+}
+
+/// Outcome of checking a chain block by block. Every problem found is listed,
+/// not just the first one.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub blocks_checked: usize,
+    pub failed_height: Option<usize>,
+    pub ledger_consistent: bool,
+    pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProofSide {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MerkleProofStep {
+    pub hash: String,
+    pub side: ProofSide,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InclusionProofDTO {
+    pub election_id: String,
+    pub transaction_hash: String,
+    pub block_height: usize,
+    pub block_hash: String,
+    pub merkle_root: String,
+    pub proof: Vec<MerkleProofStep>,
+}
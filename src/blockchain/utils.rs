@@ -0,0 +1,253 @@
+use std::sync::OnceLock;
+
+use ed25519_compact::{KeyPair, Seed};
+use jwt::ToBase64;
+use sha2::{Digest, Sha256};
+
+use super::{
+    encoding::{CanonicalEncoder, COMMITMENT_DOMAIN},
+    types::{BlockchainError, MerkleProofStep, ProofSide},
+};
+
+// Leaves and interior nodes are hashed with distinct prefixes so an interior
+// node can never be passed off as a transaction hash.
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+const COMMITMENT_VERSION: u8 = 1;
+
+struct AuthorityKeys {
+    current: KeyPair,
+    retired: Vec<KeyPair>,
+}
+
+static AUTHORITY_KEYS: OnceLock<AuthorityKeys> = OnceLock::new();
+
+fn authority_key_from_seed(name: &str, seed: &str) -> Result<KeyPair, BlockchainError> {
+    let seed = hex::decode(seed.trim()).map_err(|_| BlockchainError::InternalError {
+        content: format!("{} should be hex encoded", name),
+    })?;
+    let seed = Seed::from_slice(&seed).map_err(|_| BlockchainError::InternalError {
+        content: format!("{} should be 32 bytes", name),
+    })?;
+    Ok(KeyPair::from_seed(seed))
+}
+
+/// Loads the election authority key that signs block headers from the
+/// hex-encoded 32-byte seed in `AUTHORITY_SEED`, and the keys it replaced from
+/// the comma separated seeds in `RETIRED_AUTHORITY_SEEDS`, so chains started
+/// under them can still be sealed. Called once at startup, which fails without
+/// a valid seed rather than sign with a key that is gone after a restart.
+pub fn load_authority_keys() -> Result<(), BlockchainError> {
+    let seed = std::env::var("AUTHORITY_SEED").map_err(|_| BlockchainError::InternalError {
+        content: "AUTHORITY_SEED is not set".into(),
+    })?;
+    let current = authority_key_from_seed("AUTHORITY_SEED", &seed)?;
+    let retired = match std::env::var("RETIRED_AUTHORITY_SEEDS") {
+        Ok(seeds) => seeds
+            .split(',')
+            .filter(|seed| !seed.trim().is_empty())
+            .map(|seed| authority_key_from_seed("RETIRED_AUTHORITY_SEEDS", seed))
+            .collect::<Result<Vec<KeyPair>, BlockchainError>>()?,
+        Err(_) => vec![],
+    };
+    let _ = AUTHORITY_KEYS.set(AuthorityKeys { current, retired });
+    Ok(())
+}
+
+fn authority_keys() -> &'static AuthorityKeys {
+    AUTHORITY_KEYS
+        .get()
+        .expect("authority keys are loaded at startup")
+}
+
+/// The key new elections are started with.
+pub fn authority_key() -> &'static KeyPair {
+    &authority_keys().current
+}
+
+/// The key to seal blocks with for a chain whose genesis names `public_key`.
+/// A chain naming a key the server no longer holds is sealed with the current
+/// key, and validation reports those blocks.
+pub fn authority_key_for(public_key: &str) -> &'static KeyPair {
+    let keys = authority_keys();
+    std::iter::once(&keys.current)
+        .chain(keys.retired.iter())
+        .find(|pair| pair.pk.to_base64().is_ok_and(|pk| pk == public_key))
+        .unwrap_or(&keys.current)
+}
+
+pub fn authority_public_key() -> String {
+    authority_key().pk.to_base64().unwrap().to_string()
+}
+
+fn hash_leaf(leaf: &str) -> Vec<u8> {
+    let mut hash = Sha256::new();
+    hash.update([LEAF_PREFIX]);
+    hash.update(leaf.as_bytes());
+    hash.finalize().to_vec()
+}
+
+fn hash_nodes(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new();
+    hash.update([NODE_PREFIX]);
+    hash.update(left);
+    hash.update(right);
+    hash.finalize().to_vec()
+}
+
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_nodes(left, right),
+            // an unpaired node is carried up unchanged
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Computes the merkle root over a block's transaction hashes. An empty block
+/// commits to the hash of the empty string.
+pub fn merkle_root(leaves: &[String]) -> String {
+    if leaves.is_empty() {
+        return format!("{:X}", Sha256::new().finalize());
+    }
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|leaf| hash_leaf(leaf)).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    hex::encode_upper(&level[0])
+}
+
+/// Collects the sibling hashes needed to recompute the root from the leaf at `index`.
+pub fn merkle_proof(leaves: &[String], index: usize) -> Option<Vec<MerkleProofStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut proof = vec![];
+    let mut position = index;
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|leaf| hash_leaf(leaf)).collect();
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            proof.push(MerkleProofStep {
+                hash: hex::encode_upper(hash),
+                side: if sibling < position {
+                    ProofSide::Left
+                } else {
+                    ProofSide::Right
+                },
+            });
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(proof)
+}
+
+/// Recomputes the root from a transaction hash and its proof. This needs nothing
+/// but the proof and the block's published merkle root, so observers can run it offline.
+pub fn verify_merkle_proof(leaf: &str, proof: &[MerkleProofStep], root: &str) -> bool {
+    let mut current = hash_leaf(leaf);
+    for step in proof {
+        let sibling = match hex::decode(&step.hash) {
+            Ok(sibling) => sibling,
+            Err(_) => return false,
+        };
+        current = match step.side {
+            ProofSide::Left => hash_nodes(&sibling, &current),
+            ProofSide::Right => hash_nodes(&current, &sibling),
+        };
+    }
+    hex::encode_upper(current) == root.to_uppercase()
+}
+
+/// Hex SHA-256 commitment to `voter` choosing `candidate` on `chain_id`, hidden by
+/// `salt` until it is revealed. Naming the voter stops a commitment being copied.
+pub fn vote_commitment(chain_id: &str, voter: &str, candidate: &str, salt: &str) -> String {
+    let mut encoder = CanonicalEncoder::new(COMMITMENT_DOMAIN, COMMITMENT_VERSION);
+    encoder
+        .string(chain_id)
+        .string(voter)
+        .string(candidate)
+        .string(salt);
+    let mut hash = Sha256::new();
+    hash.update(encoder.finish());
+    hex::encode(hash.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn known_roots() {
+        assert_eq!(
+            merkle_root(&[]),
+            "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855"
+        );
+        assert_eq!(
+            merkle_root(&leaves(&["AA"])),
+            "25A27D25E58DB964E87C725758200A07CE98B01CBD2FBFEFA5396BA937D4D5D5"
+        );
+        assert_eq!(
+            merkle_root(&leaves(&["AA", "BB"])),
+            "B93F11B59CDFE80449CD3216B0BDA7BB9C53A501A20D37F8BD7F513989D67CBE"
+        );
+        // the odd leaf is carried up and paired at the next level
+        assert_eq!(
+            merkle_root(&leaves(&["AA", "BB", "CC"])),
+            "F4EBEB48FC20F79FCA0DB9624B0321C668B428638537851A4C8C3951252D7245"
+        );
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf() {
+        for count in 1..=9 {
+            let leaves: Vec<String> = (0..count).map(|i| format!("{:02X}", i)).collect();
+            let root = merkle_root(&leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).unwrap();
+                assert!(verify_merkle_proof(leaf, &proof, &root), "{} of {}", index, count);
+            }
+            assert!(merkle_proof(&leaves, count).is_none());
+        }
+    }
+
+    #[test]
+    fn tampered_leaf_or_sibling_is_rejected() {
+        let leaves = leaves(&["AA", "BB", "CC", "DD", "EE"]);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 1).unwrap();
+        assert!(verify_merkle_proof("BB", &proof, &root));
+        assert!(!verify_merkle_proof("BC", &proof, &root));
+
+        let mut tampered = proof.clone();
+        tampered[0].hash = hex::encode_upper(hash_leaf("AB"));
+        assert!(!verify_merkle_proof("BB", &tampered, &root));
+
+        let mut swapped = proof.clone();
+        swapped[0].side = ProofSide::Right;
+        assert!(!verify_merkle_proof("BB", &swapped, &root));
+
+        assert!(!verify_merkle_proof("BB", &proof[1..], &root));
+    }
+
+    #[test]
+    fn interior_node_is_not_a_leaf() {
+        let leaves = leaves(&["AA", "BB", "CC"]);
+        let root = merkle_root(&leaves);
+        let node = hex::encode_upper(hash_nodes(&hash_leaf("AA"), &hash_leaf("BB")));
+        let proof = vec![MerkleProofStep {
+            hash: hex::encode_upper(hash_leaf("CC")),
+            side: ProofSide::Right,
+        }];
+        assert!(!verify_merkle_proof(&node, &proof, &root));
+    }
+}
@@ -20,6 +20,25 @@ pub async fn create_generic_index<T>(client: &Client, field: String, collection:
     .expect("creatring an index should succeed");
 }
 
+pub async fn create_compound_index<T>(client: &Client, fields: Vec<String>, collection: String){
+
+    let mut keys = doc!{};
+    for field in fields {
+        keys.insert(field, 1);
+    }
+    let options = IndexOptions::builder().unique(true).build();
+    let model = IndexModel::builder()
+    .keys(keys)
+    .options(options)
+    .build();
+
+    client.database(DB_NAME)
+    .collection::<T>(&collection)
+    .create_index(model,None)
+    .await
+    .expect("creatring an index should succeed");
+}
+
 pub fn to_base64(vec:&Vec<u8>) -> String {
     ToBase64::to_base64(&vec).unwrap().to_string()
 }
@@ -0,0 +1,43 @@
+use actix_web::{
+    dev::{ServiceFactory, ServiceRequest},
+    post, web, App, Error,
+};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use mongodb::Client;
+
+use crate::{
+    credential::{
+        services::{issue_credential, request_commitment},
+        types::{BlindSignDTO, CommitmentRequestDTO, CredentialError},
+    },
+    middleware::auth::user_validator,
+};
+
+#[post("/commit")]
+async fn handle_commit(
+    client: web::Data<Client>,
+    data: web::Json<CommitmentRequestDTO>,
+) -> Result<String, CredentialError> {
+    request_commitment(client, data).await
+}
+
+#[post("/sign")]
+async fn handle_sign(
+    client: web::Data<Client>,
+    data: web::Json<BlindSignDTO>,
+) -> Result<String, CredentialError> {
+    issue_credential(client, data).await
+}
+
+pub fn register_controllers<
+    T: ServiceFactory<ServiceRequest, Config = (), Error = Error, InitError = ()>,
+>(
+    app: App<T>,
+) -> App<T> {
+    app.service(
+        web::scope("/credential")
+            .service(handle_commit)
+            .service(handle_sign)
+            .wrap(HttpAuthentication::bearer(user_validator)),
+    )
+}
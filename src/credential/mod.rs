@@ -0,0 +1,5 @@
+pub mod controllers;
+pub mod models;
+pub mod services;
+pub mod types;
+pub mod utils;
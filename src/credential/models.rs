@@ -0,0 +1,28 @@
+use curve25519_dalek::Scalar;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::hex_scalar;
+
+/// Marks that a voter has had their one blind signature for an election. The
+/// ballot key it certified is never seen, so nothing else is kept.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CredentialRecord {
+    pub election_id: ObjectId,
+    pub reg_no: String,
+    pub issued_at: i64,
+}
+
+/// The one signing session an election has open. Its nonce answers a single
+/// challenge and is dropped once it has, or once the session times out, and no
+/// other voter's session opens before then. Without concurrent sessions a voter
+/// cannot pick challenges after seeing several commitments, which is what the
+/// ROS attack on blind Schnorr signatures needs.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CredentialSession {
+    pub election_id: ObjectId,
+    pub reg_no: String,
+    #[serde(with = "hex_scalar")]
+    pub nonce: Scalar,
+    pub opened_at: i64,
+}
@@ -0,0 +1,240 @@
+use actix_web::web;
+use chrono::Utc;
+use curve25519_dalek::Scalar;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client, Collection,
+};
+use serde_json::json;
+
+use crate::{
+    blockchain::types::VotingMode,
+    crypto::{
+        blind::{blind_commitment, blind_sign},
+        edwards_to_hex, random_scalar, scalar_from_hex, scalar_to_hex,
+    },
+    election::models::Election,
+    user::services::{authenticate, usable_keys},
+    DB_NAME,
+};
+
+use super::{
+    models::{CredentialRecord, CredentialSession},
+    types::{BlindSignDTO, CommitmentDTO, CommitmentRequestDTO, CredentialError},
+    utils::{credential_public_key, credential_secret, SESSION_TIMEOUT},
+};
+
+pub const CREDENTIAL_COLLECTION: &str = "credentials";
+pub const CREDENTIAL_SESSION_COLLECTION: &str = "credential_sessions";
+const ELECTION_COLLECTION: &str = "election";
+
+/// Loads an ongoing blind credential election the caller is registered for.
+async fn find_election(
+    client: &Client,
+    election_id: &str,
+    reg_no: &str,
+    password: &str,
+) -> Result<(ObjectId, Election), CredentialError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let id = match ObjectId::parse_str(election_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Err(CredentialError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = match election_collection.find_one(doc! {"_id": id}, None).await {
+        Ok(Some(election)) => election,
+        Ok(None) => {
+            return Err(CredentialError::ValidationError {
+                content: "Election not found".into(),
+            })
+        }
+        Err(e) => {
+            return Err(CredentialError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    if election.voting_mode != VotingMode::BlindCredential {
+        return Err(CredentialError::ValidationError {
+            content: "Election does not use ballot credentials".into(),
+        });
+    }
+    if election.status != "ONGOING" {
+        return Err(CredentialError::ValidationError {
+            content: "Credentials are only issued while the election is ongoing".into(),
+        });
+    }
+    let keys = match authenticate(client, reg_no, password).await {
+        Some(user) => usable_keys(client, &user).await,
+        None => {
+            return Err(CredentialError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    if !keys.iter().any(|key| election.voters.contains(key)) {
+        return Err(CredentialError::ValidationError {
+            content: "Voter is not registered for this election".into(),
+        });
+    }
+    Ok((id, election))
+}
+
+/// The election's blind signing secret, checked against the public key its
+/// chain was started with.
+fn signing_key(election_id: &ObjectId, election: &Election) -> Result<(Scalar, String), CredentialError> {
+    let secret = credential_secret(&election_id.to_hex())?;
+    let public_key = credential_public_key(&election_id.to_hex())?;
+    if election.credential_key.as_ref() != Some(&public_key) {
+        return Err(CredentialError::InternalError {
+            content: "CREDENTIAL_SEED no longer derives this election's credential key".into(),
+        });
+    }
+    Ok((secret, public_key))
+}
+
+async fn check_not_issued(
+    client: &Client,
+    election_id: &ObjectId,
+    reg_no: &str,
+) -> Result<(), CredentialError> {
+    let credential_collection: Collection<CredentialRecord> =
+        client.database(DB_NAME).collection(CREDENTIAL_COLLECTION);
+    match credential_collection
+        .find_one(doc! {"election_id": election_id, "reg_no": reg_no}, None)
+        .await
+    {
+        Ok(None) => Ok(()),
+        Ok(Some(_)) => Err(CredentialError::ValidationError {
+            content: "A credential was already issued to this voter".into(),
+        }),
+        Err(e) => Err(CredentialError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Opens the election's signing session for the caller with a fresh nonce, or
+/// hands back the commitment of the session they already have open. Fails while
+/// another voter's session is open.
+pub async fn request_commitment(
+    client: web::Data<Client>,
+    data: web::Json<CommitmentRequestDTO>,
+) -> Result<String, CredentialError> {
+    let session_collection: Collection<CredentialSession> =
+        client.database(DB_NAME).collection(CREDENTIAL_SESSION_COLLECTION);
+
+    let (election_id, election) =
+        find_election(&client, &data.election_id, &data.reg_no, &data.password).await?;
+    let (_, authority) = signing_key(&election_id, &election)?;
+    check_not_issued(&client, &election_id, &data.reg_no).await?;
+
+    let now = Utc::now().timestamp();
+    let expired = session_collection
+        .delete_many(
+            doc! {"election_id": &election_id, "opened_at": {"$lt": now - SESSION_TIMEOUT}},
+            None,
+        )
+        .await;
+    if let Err(e) = expired {
+        return Err(CredentialError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    let session = CredentialSession {
+        election_id,
+        reg_no: data.reg_no.clone(),
+        nonce: random_scalar(),
+        opened_at: now,
+    };
+    // the unique index on the election lets only one session in
+    let session = match session_collection.insert_one(&session, None).await {
+        Ok(_) => session,
+        Err(e) => match session_collection.find_one(doc! {"election_id": &election_id}, None).await {
+            Ok(Some(open)) if open.reg_no == data.reg_no => open,
+            Ok(Some(_)) => {
+                return Err(CredentialError::ValidationError {
+                    content: "Another voter is being issued a credential, try again shortly".into(),
+                })
+            }
+            _ => {
+                return Err(CredentialError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        },
+    };
+    let value = json!(CommitmentDTO {
+        commitment: edwards_to_hex(&blind_commitment(&session.nonce)),
+        authority,
+    })
+    .to_string();
+    Ok(value)
+}
+
+/// Answers the challenge for the caller's open session, which closes it.
+pub async fn issue_credential(
+    client: web::Data<Client>,
+    data: web::Json<BlindSignDTO>,
+) -> Result<String, CredentialError> {
+    let credential_collection: Collection<CredentialRecord> =
+        client.database(DB_NAME).collection(CREDENTIAL_COLLECTION);
+    let session_collection: Collection<CredentialSession> =
+        client.database(DB_NAME).collection(CREDENTIAL_SESSION_COLLECTION);
+
+    let (election_id, election) =
+        find_election(&client, &data.election_id, &data.reg_no, &data.password).await?;
+    let (secret, _) = signing_key(&election_id, &election)?;
+    let challenge = match scalar_from_hex(&data.challenge) {
+        Some(challenge) => challenge,
+        None => {
+            return Err(CredentialError::ValidationError {
+                content: "Challenge must be a hex encoded scalar".into(),
+            })
+        }
+    };
+    check_not_issued(&client, &election_id, &data.reg_no).await?;
+
+    // taking the session out before signing means its nonce answers one challenge at most
+    let cutoff = Utc::now().timestamp() - SESSION_TIMEOUT;
+    let session = session_collection
+        .find_one_and_delete(
+            doc! {"election_id": &election_id, "reg_no": &data.reg_no, "opened_at": {"$gte": cutoff}},
+            None,
+        )
+        .await;
+    let session = match session {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return Err(CredentialError::ValidationError {
+                content: "No open credential session, request a commitment first".into(),
+            })
+        }
+        Err(e) => {
+            return Err(CredentialError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    // recorded before signing: the unique index lets only one request per voter through
+    let record = CredentialRecord {
+        election_id,
+        reg_no: data.reg_no.clone(),
+        issued_at: Utc::now().timestamp(),
+    };
+    if let Err(e) = credential_collection.insert_one(&record, None).await {
+        return Err(CredentialError::InternalError {
+            content: e.to_string(),
+        });
+    }
+
+    let value = json!({
+        "response": scalar_to_hex(&blind_sign(&secret, &session.nonce, &challenge)),
+    })
+    .to_string();
+    Ok(value)
+}
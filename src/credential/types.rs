@@ -0,0 +1,55 @@
+use actix_web::{
+    error,
+    http::{header::ContentType, StatusCode},
+    HttpResponse,
+};
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Display, Error)]
+pub enum CredentialError {
+    #[display(fmt = "{}", content)]
+    ValidationError { content: String },
+
+    #[display(fmt = "{}", content)]
+    InternalError { content: String },
+}
+
+impl error::ResponseError for CredentialError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(self.to_string())
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match *self {
+            CredentialError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            CredentialError::InternalError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitmentRequestDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlindSignDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    /// Hex scalar `c' + b`, the challenge for the blinded ballot key.
+    pub challenge: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitmentDTO {
+    /// Hex Edwards point `R` the voter blinds.
+    pub commitment: String,
+    /// Hex Edwards point of the authority key credentials are checked against.
+    pub authority: String,
+}
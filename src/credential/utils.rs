@@ -0,0 +1,55 @@
+use std::sync::OnceLock;
+
+use curve25519_dalek::{constants::ED25519_BASEPOINT_POINT, Scalar};
+
+use crate::{
+    blockchain::encoding::CanonicalEncoder,
+    crypto::{challenge, edwards_to_hex},
+};
+
+use super::types::CredentialError;
+
+const CREDENTIAL_KEY_DOMAIN: &str = "devote/credential-key";
+const CREDENTIAL_KEY_VERSION: u8 = 1;
+
+/// How long a voter has to send their challenge once they have the commitment.
+pub const SESSION_TIMEOUT: i64 = 60;
+
+static CREDENTIAL_SEED: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Loads the hex-encoded 32-byte seed in `CREDENTIAL_SEED` that each election's
+/// blind signing key is derived from. It is kept apart from the block authority
+/// key, so credential sessions never sign with a key that seals blocks. Only
+/// elections with ballot credentials need it.
+pub fn load_credential_seed() -> Result<(), CredentialError> {
+    let seed = match std::env::var("CREDENTIAL_SEED") {
+        Ok(seed) => seed,
+        Err(_) => return Ok(()),
+    };
+    let seed = hex::decode(seed.trim()).map_err(|_| CredentialError::InternalError {
+        content: "CREDENTIAL_SEED should be hex encoded".into(),
+    })?;
+    if seed.len() != 32 {
+        return Err(CredentialError::InternalError {
+            content: "CREDENTIAL_SEED should be 32 bytes".into(),
+        });
+    }
+    let _ = CREDENTIAL_SEED.set(seed);
+    Ok(())
+}
+
+/// The secret `x` election `election_id` blind signs credentials with.
+pub fn credential_secret(election_id: &str) -> Result<Scalar, CredentialError> {
+    let seed = CREDENTIAL_SEED.get().ok_or_else(|| CredentialError::InternalError {
+        content: "CREDENTIAL_SEED is not set".into(),
+    })?;
+    let mut transcript = CanonicalEncoder::new(CREDENTIAL_KEY_DOMAIN, CREDENTIAL_KEY_VERSION);
+    transcript.bytes(seed).string(election_id);
+    Ok(challenge(&transcript))
+}
+
+/// Hex Edwards point `X = xG` credentials for `election_id` are checked against.
+pub fn credential_public_key(election_id: &str) -> Result<String, CredentialError> {
+    let secret = credential_secret(election_id)?;
+    Ok(edwards_to_hex(&(ED25519_BASEPOINT_POINT * secret)))
+}
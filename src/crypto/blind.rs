@@ -0,0 +1,114 @@
+//! Blind Schnorr signatures, with which the election authority certifies a
+//! voter's one-time ballot key without seeing it.
+//!
+//! The authority, with a key `X = xG` of the election's own, commits to a nonce `R = kG`. The voter
+//! blinds it as `R' = R + aG + bX`, takes `c' = H(R', X, m)` for the ballot key
+//! `m` and sends `c = c' + b`. The authority answers `s = k + cx` and the voter
+//! keeps `(R', s + a)`, a Schnorr signature on `m` that the authority cannot
+//! link back to the session it signed in.
+//!
+//! Every nonce is fresh and answers a single challenge, or the key leaks. An
+//! election runs one session at a time: with several open at once, a voter can
+//! choose challenges after seeing every commitment and forge an extra signature
+//! (the ROS attack). Each voter is signed for once, which is what limits them
+//! to one credential.
+
+use curve25519_dalek::{constants::ED25519_BASEPOINT_POINT, EdwardsPoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{challenge, hex_edwards, hex_scalar, random_scalar};
+
+const CREDENTIAL_DOMAIN: &str = "devote/credential";
+const BLIND_VERSION: u8 = 1;
+
+/// What a credential certifies: `ballot_key` may cast one ballot on `chain_id`.
+pub fn credential_message(chain_id: &str, ballot_key: &str) -> Vec<u8> {
+    let mut encoder = CanonicalEncoder::new(CREDENTIAL_DOMAIN, BLIND_VERSION);
+    encoder.string(chain_id).string(ballot_key);
+    encoder.finish()
+}
+
+fn credential_challenge(commitment: &EdwardsPoint, authority: &EdwardsPoint, message: &[u8]) -> Scalar {
+    let mut transcript = CanonicalEncoder::new(CREDENTIAL_DOMAIN, BLIND_VERSION);
+    transcript
+        .bytes(commitment.compress().as_bytes())
+        .bytes(authority.compress().as_bytes())
+        .bytes(message);
+    challenge(&transcript)
+}
+
+pub fn blind_commitment(nonce: &Scalar) -> EdwardsPoint {
+    ED25519_BASEPOINT_POINT * nonce
+}
+
+/// The authority's answer to a blinded challenge.
+pub fn blind_sign(authority_secret: &Scalar, nonce: &Scalar, blinded_challenge: &Scalar) -> Scalar {
+    nonce + blinded_challenge * authority_secret
+}
+
+/// The voter's side of a session, kept between sending the challenge and
+/// unblinding the answer.
+// run by voters on their own machines, never by the server
+#[allow(dead_code)]
+pub struct Blinding {
+    alpha: Scalar,
+    commitment: EdwardsPoint,
+}
+
+#[allow(dead_code)]
+impl Blinding {
+    /// Blinds the authority's `commitment` for `message`, returning the challenge to send.
+    pub fn new(authority: &EdwardsPoint, commitment: &EdwardsPoint, message: &[u8]) -> (Self, Scalar) {
+        let alpha = random_scalar();
+        let beta = random_scalar();
+        let commitment = commitment + ED25519_BASEPOINT_POINT * alpha + authority * beta;
+        let challenge = credential_challenge(&commitment, authority, message);
+        (Blinding { alpha, commitment }, challenge + beta)
+    }
+
+    pub fn unblind(&self, response: &Scalar) -> Credential {
+        Credential {
+            commitment: self.commitment,
+            response: response + self.alpha,
+        }
+    }
+}
+
+/// The authority's signature on a ballot key, as the voter unblinded it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Credential {
+    #[serde(with = "hex_edwards")]
+    pub commitment: EdwardsPoint,
+    #[serde(with = "hex_scalar")]
+    pub response: Scalar,
+}
+
+impl Credential {
+    pub fn verify(&self, authority: &EdwardsPoint, message: &[u8]) -> bool {
+        let challenge = credential_challenge(&self.commitment, authority, message);
+        self.commitment.is_torsion_free()
+            && ED25519_BASEPOINT_POINT * self.response == self.commitment + authority * challenge
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unblinded_credential_verifies() {
+        let secret = random_scalar();
+        let authority = ED25519_BASEPOINT_POINT * secret;
+        let nonce = random_scalar();
+        let message = credential_message("65f0c0ffee0000000000beef", "ballot-key");
+
+        let (blinding, challenge) = Blinding::new(&authority, &blind_commitment(&nonce), &message);
+        let credential = blinding.unblind(&blind_sign(&secret, &nonce, &challenge));
+
+        assert!(credential.verify(&authority, &message));
+        assert!(!credential.verify(&authority, &credential_message("65f0c0ffee0000000000beef", "other")));
+        assert!(!credential.verify(&(ED25519_BASEPOINT_POINT * random_scalar()), &message));
+    }
+}
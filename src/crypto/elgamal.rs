@@ -0,0 +1,88 @@
+use std::ops::Add;
+
+use curve25519_dalek::{traits::Identity, RistrettoPoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{generator, hex_point, random_scalar};
+
+/// Exponential ElGamal ciphertext `(rG, mG + rY)` for election key `Y`. Adding
+/// ciphertexts adds the messages, so a tally is the sum of the ballots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Ciphertext {
+    #[serde(with = "hex_point")]
+    pub c1: RistrettoPoint,
+    #[serde(with = "hex_point")]
+    pub c2: RistrettoPoint,
+}
+
+impl Ciphertext {
+    pub fn encrypt(election_key: &RistrettoPoint, message: u64, randomness: &Scalar) -> Self {
+        Ciphertext {
+            c1: generator() * randomness,
+            c2: generator() * Scalar::from(message) + election_key * randomness,
+        }
+    }
+
+    /// Encryption of zero with no randomness, the starting point of a sum.
+    pub fn zero() -> Self {
+        Ciphertext {
+            c1: RistrettoPoint::identity(),
+            c2: RistrettoPoint::identity(),
+        }
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .bytes(self.c1.compress().as_bytes())
+            .bytes(self.c2.compress().as_bytes());
+    }
+}
+
+impl Add for Ciphertext {
+    type Output = Ciphertext;
+
+    fn add(self, other: Ciphertext) -> Ciphertext {
+        Ciphertext {
+            c1: self.c1 + other.c1,
+            c2: self.c2 + other.c2,
+        }
+    }
+}
+
+/// Encrypts a selection of candidate `choice` out of `candidates` as 1 in its
+/// position and 0 everywhere else, returning the randomness used for each.
+pub fn encrypt_choice(election_key: &RistrettoPoint, candidates: usize, choice: usize) -> (Vec<Ciphertext>, Vec<Scalar>) {
+    let randomness: Vec<Scalar> = (0..candidates).map(|_| random_scalar()).collect();
+    let ciphertexts = randomness
+        .iter()
+        .enumerate()
+        .map(|(index, r)| Ciphertext::encrypt(election_key, (index == choice) as u64, r))
+        .collect();
+    (ciphertexts, randomness)
+}
+
+/// Sums ballots position by position; every ballot holds one ciphertext per candidate.
+pub fn sum_ballots<'a>(ballots: impl Iterator<Item = &'a Vec<Ciphertext>>, candidates: usize) -> Vec<Ciphertext> {
+    let mut totals = vec![Ciphertext::zero(); candidates];
+    for ballot in ballots {
+        for (total, ciphertext) in totals.iter_mut().zip(ballot.iter()) {
+            *total = *total + *ciphertext;
+        }
+    }
+    totals
+}
+
+/// Recovers `m` from `mG` by counting up from zero; `max` bounds the search,
+/// which for a tally is the number of ballots.
+pub fn discrete_log(point: &RistrettoPoint, max: u64) -> Option<u64> {
+    let mut candidate = RistrettoPoint::identity();
+    for m in 0..=max {
+        if candidate == *point {
+            return Some(m);
+        }
+        candidate += generator();
+    }
+    None
+}
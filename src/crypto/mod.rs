@@ -0,0 +1,160 @@
+//! Group arithmetic for encrypted ballots, over the Ristretto group, and for
+//! anonymous ballots, over the Edwards curve behind ed25519 keys.
+//!
+//! Points and scalars are exchanged as lowercase hex of their 32-byte
+//! encodings. Challenges for the zero-knowledge proofs are derived by hashing a
+//! canonical transcript (see `blockchain::encoding`) to a scalar with SHA-512.
+
+pub mod blind;
+pub mod elgamal;
+pub mod proofs;
+pub mod ring;
+pub mod shuffle;
+pub mod threshold;
+
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT, edwards::CompressedEdwardsY, ristretto::CompressedRistretto,
+    EdwardsPoint, RistrettoPoint, Scalar,
+};
+use ed25519_compact::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha512};
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+pub fn generator() -> RistrettoPoint {
+    RISTRETTO_BASEPOINT_POINT
+}
+
+pub fn random_scalar() -> Scalar {
+    Scalar::random(&mut OsRng)
+}
+
+pub fn point_to_hex(point: &RistrettoPoint) -> String {
+    hex::encode(point.compress().as_bytes())
+}
+
+pub fn point_from_hex(value: &str) -> Option<RistrettoPoint> {
+    let bytes = hex::decode(value).ok()?;
+    CompressedRistretto::from_slice(&bytes).ok()?.decompress()
+}
+
+pub fn scalar_to_hex(scalar: &Scalar) -> String {
+    hex::encode(scalar.as_bytes())
+}
+
+pub fn scalar_from_hex(value: &str) -> Option<Scalar> {
+    let bytes: [u8; 32] = hex::decode(value).ok()?.try_into().ok()?;
+    Option::from(Scalar::from_canonical_bytes(bytes))
+}
+
+/// The Edwards point behind an ed25519 public key.
+pub fn edwards_point(public_key: &PublicKey) -> Option<EdwardsPoint> {
+    CompressedEdwardsY::from_slice(public_key.as_ref()).ok()?.decompress()
+}
+
+/// The scalar an ed25519 secret key signs with: the clamped low half of SHA-512 of its seed.
+// run by voters ring signing with their own key, never by the server
+#[allow(dead_code)]
+pub fn secret_scalar(secret_key: &SecretKey) -> Scalar {
+    let hash = Sha512::digest(secret_key.seed().as_ref());
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&hash[..32]);
+    bytes[0] &= 248;
+    bytes[31] &= 127;
+    bytes[31] |= 64;
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+pub fn edwards_to_hex(point: &EdwardsPoint) -> String {
+    hex::encode(point.compress().as_bytes())
+}
+
+pub fn edwards_from_hex(value: &str) -> Option<EdwardsPoint> {
+    let bytes = hex::decode(value).ok()?;
+    CompressedEdwardsY::from_slice(&bytes).ok()?.decompress()
+}
+
+/// Fiat-Shamir challenge over a transcript built by the caller.
+pub fn challenge(transcript: &CanonicalEncoder) -> Scalar {
+    Scalar::hash_from_bytes::<Sha512>(&transcript.finish())
+}
+
+/// Serde adapter storing a point as hex.
+pub mod hex_point {
+    use curve25519_dalek::RistrettoPoint;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(point: &RistrettoPoint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::point_to_hex(point))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RistrettoPoint, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::point_from_hex(&value).ok_or_else(|| D::Error::custom("invalid ristretto point"))
+    }
+}
+
+/// Serde adapter storing an Edwards point as hex.
+pub mod hex_edwards {
+    use curve25519_dalek::EdwardsPoint;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(point: &EdwardsPoint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::edwards_to_hex(point))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EdwardsPoint, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::edwards_from_hex(&value).ok_or_else(|| D::Error::custom("invalid edwards point"))
+    }
+}
+
+/// Serde adapter storing a scalar as hex.
+pub mod hex_scalar {
+    use curve25519_dalek::Scalar;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(scalar: &Scalar, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::scalar_to_hex(scalar))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Scalar, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::scalar_from_hex(&value).ok_or_else(|| D::Error::custom("invalid scalar"))
+    }
+}
+
+/// Serde adapter storing a list of points as hex.
+pub mod hex_points {
+    use curve25519_dalek::RistrettoPoint;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(points: &[RistrettoPoint], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(points.iter().map(super::point_to_hex))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<RistrettoPoint>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| super::point_from_hex(value).ok_or_else(|| D::Error::custom("invalid ristretto point")))
+            .collect()
+    }
+}
+
+/// Serde adapter storing a list of scalars as hex.
+pub mod hex_scalars {
+    use curve25519_dalek::Scalar;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(scalars: &[Scalar], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(scalars.iter().map(super::scalar_to_hex))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Scalar>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| super::scalar_from_hex(value).ok_or_else(|| D::Error::custom("invalid scalar")))
+            .collect()
+    }
+}
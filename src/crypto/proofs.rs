@@ -0,0 +1,395 @@
+use curve25519_dalek::{RistrettoPoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{challenge, elgamal::Ciphertext, generator, hex_scalar, random_scalar};
+
+const DLEQ_DOMAIN: &str = "devote/dleq";
+const BIT_DOMAIN: &str = "devote/ballot-bit";
+const TOTAL_DOMAIN: &str = "devote/ballot-total";
+const PROOF_VERSION: u8 = 1;
+
+/// Who a ballot proof was made for. It goes into every challenge, so a proof
+/// copied onto another voter's or another election's ballot does not verify.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BallotContext<'a> {
+    pub election_id: &'a str,
+    pub voter: &'a str,
+}
+
+impl BallotContext<'_> {
+    fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encoder.string(self.election_id).string(self.voter);
+    }
+}
+
+/// Chaum-Pedersen proof that `public = xG` and `result = x * base` share the
+/// same secret `x`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DleqProof {
+    #[serde(with = "hex_scalar")]
+    pub challenge: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub response: Scalar,
+}
+
+impl DleqProof {
+    pub fn prove(
+        secret: &Scalar,
+        base: &RistrettoPoint,
+        public: &RistrettoPoint,
+        result: &RistrettoPoint,
+    ) -> Self {
+        Self::prove_in(None, secret, base, public, result)
+    }
+
+    pub fn verify(&self, base: &RistrettoPoint, public: &RistrettoPoint, result: &RistrettoPoint) -> bool {
+        self.verify_in(None, base, public, result)
+    }
+
+    fn prove_in(
+        context: Option<&BallotContext>,
+        secret: &Scalar,
+        base: &RistrettoPoint,
+        public: &RistrettoPoint,
+        result: &RistrettoPoint,
+    ) -> Self {
+        let nonce = random_scalar();
+        let challenge = dleq_challenge(
+            context,
+            base,
+            public,
+            result,
+            &(generator() * nonce),
+            &(base * nonce),
+        );
+        DleqProof {
+            challenge,
+            response: nonce + challenge * secret,
+        }
+    }
+
+    fn verify_in(
+        &self,
+        context: Option<&BallotContext>,
+        base: &RistrettoPoint,
+        public: &RistrettoPoint,
+        result: &RistrettoPoint,
+    ) -> bool {
+        let commitment_g = generator() * self.response - public * self.challenge;
+        let commitment_base = base * self.response - result * self.challenge;
+        dleq_challenge(context, base, public, result, &commitment_g, &commitment_base) == self.challenge
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encoder
+            .bytes(self.challenge.as_bytes())
+            .bytes(self.response.as_bytes());
+    }
+}
+
+/// Ballot totals are proven under their own domain, after the ballot's context.
+fn dleq_challenge(
+    context: Option<&BallotContext>,
+    base: &RistrettoPoint,
+    public: &RistrettoPoint,
+    result: &RistrettoPoint,
+    commitment_g: &RistrettoPoint,
+    commitment_base: &RistrettoPoint,
+) -> Scalar {
+    let mut transcript = match context {
+        Some(context) => {
+            let mut transcript = CanonicalEncoder::new(TOTAL_DOMAIN, PROOF_VERSION);
+            context.encode(&mut transcript);
+            transcript
+        }
+        None => CanonicalEncoder::new(DLEQ_DOMAIN, PROOF_VERSION),
+    };
+    for point in [base, public, result, commitment_g, commitment_base] {
+        transcript.bytes(point.compress().as_bytes());
+    }
+    challenge(&transcript)
+}
+
+/// Disjunctive Chaum-Pedersen proof that a ciphertext encrypts 0 or 1, without
+/// saying which. The branch that is not true is simulated; the two challenges
+/// must add up to the Fiat-Shamir challenge, so only one can be chosen freely.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BitProof {
+    #[serde(with = "hex_scalar")]
+    pub challenge_zero: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub challenge_one: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub response_zero: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub response_one: Scalar,
+}
+
+impl BitProof {
+    pub fn prove(
+        context: &BallotContext,
+        election_key: &RistrettoPoint,
+        ciphertext: &Ciphertext,
+        bit: bool,
+        randomness: &Scalar,
+    ) -> Self {
+        let simulated_challenge = random_scalar();
+        let simulated_response = random_scalar();
+        let nonce = random_scalar();
+
+        // commitments for "encrypts 0" and "encrypts 1", real or simulated
+        let simulated = bit_commitments(election_key, ciphertext, !bit, &simulated_challenge, &simulated_response);
+        let real = (generator() * nonce, election_key * nonce);
+        let (zero, one) = if bit { (simulated, real) } else { (real, simulated) };
+
+        let challenge = bit_challenge(context, election_key, ciphertext, &zero, &one);
+        let real_challenge = challenge - simulated_challenge;
+        let real_response = nonce + real_challenge * randomness;
+        if bit {
+            BitProof {
+                challenge_zero: simulated_challenge,
+                challenge_one: real_challenge,
+                response_zero: simulated_response,
+                response_one: real_response,
+            }
+        } else {
+            BitProof {
+                challenge_zero: real_challenge,
+                challenge_one: simulated_challenge,
+                response_zero: real_response,
+                response_one: simulated_response,
+            }
+        }
+    }
+
+    pub fn verify(&self, context: &BallotContext, election_key: &RistrettoPoint, ciphertext: &Ciphertext) -> bool {
+        let zero = bit_commitments(election_key, ciphertext, false, &self.challenge_zero, &self.response_zero);
+        let one = bit_commitments(election_key, ciphertext, true, &self.challenge_one, &self.response_one);
+        bit_challenge(context, election_key, ciphertext, &zero, &one) == self.challenge_zero + self.challenge_one
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        for scalar in [&self.challenge_zero, &self.challenge_one, &self.response_zero, &self.response_one] {
+            encoder.bytes(scalar.as_bytes());
+        }
+    }
+}
+
+/// Commitments a verifier recomputes for the claim that `ciphertext` encrypts `bit`.
+fn bit_commitments(
+    election_key: &RistrettoPoint,
+    ciphertext: &Ciphertext,
+    bit: bool,
+    challenge: &Scalar,
+    response: &Scalar,
+) -> (RistrettoPoint, RistrettoPoint) {
+    let message = if bit { generator() } else { RistrettoPoint::default() };
+    (
+        generator() * response - ciphertext.c1 * challenge,
+        election_key * response - (ciphertext.c2 - message) * challenge,
+    )
+}
+
+fn bit_challenge(
+    context: &BallotContext,
+    election_key: &RistrettoPoint,
+    ciphertext: &Ciphertext,
+    zero: &(RistrettoPoint, RistrettoPoint),
+    one: &(RistrettoPoint, RistrettoPoint),
+) -> Scalar {
+    let mut transcript = CanonicalEncoder::new(BIT_DOMAIN, PROOF_VERSION);
+    context.encode(&mut transcript);
+    transcript.bytes(election_key.compress().as_bytes());
+    ciphertext.encode(&mut transcript);
+    for point in [&zero.0, &zero.1, &one.0, &one.1] {
+        transcript.bytes(point.compress().as_bytes());
+    }
+    challenge(&transcript)
+}
+
+/// Proof that an encrypted ballot is well formed: every ciphertext encrypts 0
+/// or 1, and together they encrypt exactly the number of selections allowed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BallotProof {
+    pub choices: Vec<BitProof>,
+    /// Shows the summed ciphertext minus the allowed selections encrypts zero.
+    pub total: DleqProof,
+}
+
+impl BallotProof {
+    /// Proves a ballot selecting `choice`, from the randomness each ciphertext was made with.
+    pub fn prove(
+        context: &BallotContext,
+        election_key: &RistrettoPoint,
+        ciphertexts: &[Ciphertext],
+        randomness: &[Scalar],
+        choice: usize,
+    ) -> Self {
+        let choices = ciphertexts
+            .iter()
+            .zip(randomness.iter())
+            .enumerate()
+            .map(|(index, (ciphertext, r))| BitProof::prove(context, election_key, ciphertext, index == choice, r))
+            .collect();
+        let total = sum(ciphertexts);
+        let total_randomness: Scalar = randomness.iter().sum();
+        BallotProof {
+            choices,
+            total: DleqProof::prove_in(
+                Some(context),
+                &total_randomness,
+                election_key,
+                &total.c1,
+                &(total.c2 - generator()),
+            ),
+        }
+    }
+
+    pub fn verify(
+        &self,
+        context: &BallotContext,
+        election_key: &RistrettoPoint,
+        ciphertexts: &[Ciphertext],
+        selections: u64,
+    ) -> bool {
+        if self.choices.len() != ciphertexts.len() {
+            return false;
+        }
+        let total = sum(ciphertexts);
+        self.choices
+            .iter()
+            .zip(ciphertexts.iter())
+            .all(|(proof, ciphertext)| proof.verify(context, election_key, ciphertext))
+            && self.total.verify_in(
+                Some(context),
+                election_key,
+                &total.c1,
+                &(total.c2 - generator() * Scalar::from(selections)),
+            )
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encoder.count(self.choices.len());
+        for proof in self.choices.iter() {
+            proof.encode(encoder);
+        }
+        self.total.encode(encoder);
+    }
+}
+
+fn sum(ciphertexts: &[Ciphertext]) -> Ciphertext {
+    ciphertexts.iter().fold(Ciphertext::zero(), |acc, c| acc + *c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::elgamal::encrypt_choice;
+
+    const CONTEXT: BallotContext = BallotContext {
+        election_id: "65f0c0ffee0000000000beef",
+        voter: "voter-a",
+    };
+
+    fn keypair() -> (Scalar, RistrettoPoint) {
+        let secret = random_scalar();
+        (secret, generator() * secret)
+    }
+
+    #[test]
+    fn dleq_proves_a_shared_secret() {
+        let (secret, public) = keypair();
+        let base = generator() * random_scalar();
+        let proof = DleqProof::prove(&secret, &base, &public, &(base * secret));
+
+        assert!(proof.verify(&base, &public, &(base * secret)));
+        assert!(!proof.verify(&base, &public, &(base * random_scalar())));
+    }
+
+    #[test]
+    fn bit_proofs_round_trip_for_either_bit() {
+        let (_, key) = keypair();
+        for bit in [false, true] {
+            let r = random_scalar();
+            let ciphertext = Ciphertext::encrypt(&key, bit as u64, &r);
+            let proof = BitProof::prove(&CONTEXT, &key, &ciphertext, bit, &r);
+            assert!(proof.verify(&CONTEXT, &key, &ciphertext));
+        }
+    }
+
+    #[test]
+    fn bit_proof_rejects_a_flipped_bit() {
+        let (_, key) = keypair();
+        let r = random_scalar();
+        let zero = Ciphertext::encrypt(&key, 0, &r);
+        let one = Ciphertext::encrypt(&key, 1, &r);
+
+        // claiming the wrong bit, or keeping the proof and flipping the plaintext
+        assert!(!BitProof::prove(&CONTEXT, &key, &zero, true, &r).verify(&CONTEXT, &key, &zero));
+        assert!(!BitProof::prove(&CONTEXT, &key, &zero, false, &r).verify(&CONTEXT, &key, &one));
+        // a two is neither bit
+        let two = Ciphertext::encrypt(&key, 2, &r);
+        assert!(!BitProof::prove(&CONTEXT, &key, &two, true, &r).verify(&CONTEXT, &key, &two));
+    }
+
+    #[test]
+    fn ballot_proof_round_trips() {
+        let (_, key) = keypair();
+        let (ciphertexts, randomness) = encrypt_choice(&key, 4, 2);
+        let proof = BallotProof::prove(&CONTEXT, &key, &ciphertexts, &randomness, 2);
+
+        assert!(proof.verify(&CONTEXT, &key, &ciphertexts, 1));
+    }
+
+    #[test]
+    fn ballot_proof_rejects_a_wrong_sum() {
+        let (_, key) = keypair();
+        let randomness: Vec<Scalar> = (0..3).map(|_| random_scalar()).collect();
+        // votes for the first two candidates at once
+        let ciphertexts: Vec<Ciphertext> = [1, 1, 0]
+            .iter()
+            .zip(randomness.iter())
+            .map(|(bit, r)| Ciphertext::encrypt(&key, *bit, r))
+            .collect();
+        let proof = BallotProof::prove(&CONTEXT, &key, &ciphertexts, &randomness, 0);
+        assert!(!proof.verify(&CONTEXT, &key, &ciphertexts, 1));
+
+        let (ciphertexts, randomness) = encrypt_choice(&key, 3, 1);
+        let proof = BallotProof::prove(&CONTEXT, &key, &ciphertexts, &randomness, 1);
+        assert!(!proof.verify(&CONTEXT, &key, &ciphertexts, 2));
+    }
+
+    #[test]
+    fn ballot_proof_rejects_swapped_or_missing_ciphertexts() {
+        let (_, key) = keypair();
+        let (mut ciphertexts, randomness) = encrypt_choice(&key, 3, 0);
+        let proof = BallotProof::prove(&CONTEXT, &key, &ciphertexts, &randomness, 0);
+
+        ciphertexts.swap(0, 1);
+        assert!(!proof.verify(&CONTEXT, &key, &ciphertexts, 1));
+        ciphertexts.swap(0, 1);
+        ciphertexts[2] = Ciphertext::encrypt(&key, 0, &random_scalar());
+        assert!(!proof.verify(&CONTEXT, &key, &ciphertexts, 1));
+        assert!(!proof.verify(&CONTEXT, &key, &ciphertexts[..2], 1));
+    }
+
+    #[test]
+    fn ballot_proof_is_bound_to_voter_and_election() {
+        let (_, key) = keypair();
+        let (ciphertexts, randomness) = encrypt_choice(&key, 3, 1);
+        let proof = BallotProof::prove(&CONTEXT, &key, &ciphertexts, &randomness, 1);
+
+        let other_voter = BallotContext {
+            voter: "voter-b",
+            ..CONTEXT
+        };
+        let other_election = BallotContext {
+            election_id: "65f0c0ffee0000000000cafe",
+            ..CONTEXT
+        };
+        assert!(!proof.verify(&other_voter, &key, &ciphertexts, 1));
+        assert!(!proof.verify(&other_election, &key, &ciphertexts, 1));
+    }
+}
@@ -0,0 +1,166 @@
+//! Linkable ring signatures (LSAG) over voters' ed25519 keys.
+//!
+//! A signature shows the signer holds the secret key of one member of the ring
+//! without saying which. Every signature by the same key carries the same key
+//! image `I = x * Hp(P)`, so a second vote is recognisable while the first
+//! stays anonymous. Ring members are ed25519 public keys; `Hp` hashes into the
+//! Ristretto group, which has the same prime order, so one scalar serves both.
+
+use curve25519_dalek::{constants::ED25519_BASEPOINT_POINT, EdwardsPoint, RistrettoPoint, Scalar};
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{challenge, hex_point, hex_scalar, hex_scalars, random_scalar};
+
+const RING_DOMAIN: &str = "devote/ring";
+const KEY_IMAGE_DOMAIN: &str = "devote/key-image";
+const RING_VERSION: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RingSignature {
+    #[serde(with = "hex_point")]
+    pub key_image: RistrettoPoint,
+    /// Challenge for the first ring member; the rest are recomputed around the ring.
+    #[serde(with = "hex_scalar")]
+    pub challenge: Scalar,
+    #[serde(with = "hex_scalars")]
+    pub responses: Vec<Scalar>,
+}
+
+fn hash_to_point(member: &EdwardsPoint) -> RistrettoPoint {
+    let mut encoder = CanonicalEncoder::new(KEY_IMAGE_DOMAIN, RING_VERSION);
+    encoder.bytes(member.compress().as_bytes());
+    RistrettoPoint::hash_from_bytes::<Sha512>(&encoder.finish())
+}
+
+pub fn key_image(secret: &Scalar, member: &EdwardsPoint) -> RistrettoPoint {
+    hash_to_point(member) * secret
+}
+
+fn ring_challenge(
+    message: &[u8],
+    ring: &[EdwardsPoint],
+    key_image: &RistrettoPoint,
+    left: &EdwardsPoint,
+    right: &RistrettoPoint,
+) -> Scalar {
+    let mut transcript = CanonicalEncoder::new(RING_DOMAIN, RING_VERSION);
+    transcript.bytes(message).count(ring.len());
+    for member in ring {
+        transcript.bytes(member.compress().as_bytes());
+    }
+    transcript
+        .bytes(key_image.compress().as_bytes())
+        .bytes(left.compress().as_bytes())
+        .bytes(right.compress().as_bytes());
+    challenge(&transcript)
+}
+
+impl RingSignature {
+    /// Signs `message` as `ring[signer]`, whose secret is `secret`.
+    pub fn sign(message: &[u8], ring: &[EdwardsPoint], signer: usize, secret: &Scalar) -> Self {
+        let n = ring.len();
+        let image = key_image(secret, &ring[signer]);
+        let mut challenges = vec![Scalar::ZERO; n];
+        let mut responses: Vec<Scalar> = (0..n).map(|_| random_scalar()).collect();
+
+        let nonce = random_scalar();
+        let mut left = ED25519_BASEPOINT_POINT * nonce;
+        let mut right = hash_to_point(&ring[signer]) * nonce;
+        for step in 1..=n {
+            let i = (signer + step) % n;
+            challenges[i] = ring_challenge(message, ring, &image, &left, &right);
+            if i == signer {
+                break;
+            }
+            left = ED25519_BASEPOINT_POINT * responses[i] + ring[i] * challenges[i];
+            right = hash_to_point(&ring[i]) * responses[i] + image * challenges[i];
+        }
+        responses[signer] = nonce - challenges[signer] * secret;
+
+        RingSignature {
+            key_image: image,
+            challenge: challenges[0],
+            responses,
+        }
+    }
+
+    pub fn verify(&self, message: &[u8], ring: &[EdwardsPoint]) -> bool {
+        if ring.is_empty() || self.responses.len() != ring.len() {
+            return false;
+        }
+        let mut challenge = self.challenge;
+        for (member, response) in ring.iter().zip(self.responses.iter()) {
+            let left = ED25519_BASEPOINT_POINT * response + member * challenge;
+            let right = hash_to_point(member) * response + self.key_image * challenge;
+            challenge = ring_challenge(message, ring, &self.key_image, &left, &right);
+        }
+        challenge == self.challenge
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_compact::{KeyPair, Seed};
+
+    use super::*;
+    use crate::crypto::{edwards_point, secret_scalar};
+
+    fn voters(count: usize) -> (Vec<EdwardsPoint>, Vec<Scalar>) {
+        (0..count)
+            .map(|_| {
+                let pair = KeyPair::from_seed(Seed::generate());
+                (edwards_point(&pair.pk).unwrap(), secret_scalar(&pair.sk))
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn signature_verifies_for_every_position() {
+        let (ring, secrets) = voters(4);
+        for (signer, secret) in secrets.iter().enumerate() {
+            let signature = RingSignature::sign(b"ballot", &ring, signer, secret);
+            assert!(signature.verify(b"ballot", &ring));
+        }
+    }
+
+    #[test]
+    fn modified_message_fails() {
+        let (ring, secrets) = voters(3);
+        let signature = RingSignature::sign(b"ballot", &ring, 1, &secrets[1]);
+
+        assert!(!signature.verify(b"ballot!", &ring));
+    }
+
+    #[test]
+    fn non_member_cannot_sign() {
+        let (ring, _) = voters(3);
+        let (_, outsider) = voters(1);
+        let signature = RingSignature::sign(b"ballot", &ring, 0, &outsider[0]);
+
+        assert!(!signature.verify(b"ballot", &ring));
+    }
+
+    #[test]
+    fn changed_ring_fails() {
+        let (mut ring, secrets) = voters(3);
+        let signature = RingSignature::sign(b"ballot", &ring, 0, &secrets[0]);
+
+        ring.swap(1, 2);
+        assert!(!signature.verify(b"ballot", &ring));
+        assert!(!signature.verify(b"ballot", &ring[..2]));
+    }
+
+    #[test]
+    fn same_key_links_across_signatures() {
+        let (ring, secrets) = voters(3);
+        let first = RingSignature::sign(b"first", &ring, 2, &secrets[2]);
+        let second = RingSignature::sign(b"second", &ring, 2, &secrets[2]);
+        let other = RingSignature::sign(b"first", &ring, 0, &secrets[0]);
+
+        assert_eq!(first.key_image, second.key_image);
+        assert_ne!(first.key_image, other.key_image);
+    }
+}
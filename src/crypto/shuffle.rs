@@ -0,0 +1,461 @@
+//! Verifiable re-encryption shuffles of encrypted ballots.
+//!
+//! A mixer re-encrypts every ballot and puts them in a secret order, so no
+//! ballot can be matched to the one it came from. The proof is the shuffle
+//! argument of Terelius and Wikström, as laid out in the pseudo-code of Haenni
+//! et al.: a commitment to the permutation matrix, a chain of commitments to
+//! the permuted challenges, and one Schnorr-style proof tying them to the
+//! re-encryptions. Every ciphertext of a ballot moves with the same permutation.
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+use rand::{rngs::OsRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{challenge, elgamal::Ciphertext, generator, hex_points, hex_scalar, hex_scalars, random_scalar};
+
+const SHUFFLE_DOMAIN: &str = "devote/shuffle";
+const WEIGHT_DOMAIN: &str = "devote/shuffle-weight";
+const GENERATOR_DOMAIN: &str = "devote/shuffle-generator";
+const SHUFFLE_VERSION: u8 = 1;
+
+/// One ciphertext per candidate.
+pub type EncryptedBallot = Vec<Ciphertext>;
+
+pub fn reencrypt(election_key: &RistrettoPoint, ciphertext: &Ciphertext, randomness: &Scalar) -> Ciphertext {
+    Ciphertext {
+        c1: ciphertext.c1 + generator() * randomness,
+        c2: ciphertext.c2 + election_key * randomness,
+    }
+}
+
+/// Output `i` re-encrypts input `permutation[i]` with `randomness[i]`.
+// run by trustees on their own machines, never by the server
+#[allow(dead_code)]
+pub fn shuffle(
+    election_key: &RistrettoPoint,
+    ballots: &[EncryptedBallot],
+    permutation: &[usize],
+    randomness: &[Vec<Scalar>],
+) -> Vec<EncryptedBallot> {
+    permutation
+        .iter()
+        .zip(randomness.iter())
+        .map(|(source, r)| {
+            ballots[*source]
+                .iter()
+                .zip(r.iter())
+                .map(|(ciphertext, r)| reencrypt(election_key, ciphertext, r))
+                .collect()
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+pub fn random_permutation(size: usize) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..size).collect();
+    permutation.shuffle(&mut OsRng);
+    permutation
+}
+
+/// Independent generators for the permutation commitment, and the base of the
+/// challenge chain after them. Nobody knows their discrete logs.
+fn generators(size: usize) -> (Vec<RistrettoPoint>, RistrettoPoint) {
+    let generator_at = |index: u64| {
+        let mut encoder = CanonicalEncoder::new(GENERATOR_DOMAIN, SHUFFLE_VERSION);
+        encoder.u64(index);
+        RistrettoPoint::hash_from_bytes::<Sha512>(&encoder.finish())
+    };
+    let generators = (0..size as u64).map(generator_at).collect();
+    (generators, generator_at(u64::MAX))
+}
+
+fn encode_ballots(encoder: &mut CanonicalEncoder, ballots: &[EncryptedBallot]) {
+    encoder.count(ballots.len());
+    for ballot in ballots {
+        encoder.count(ballot.len());
+        for ciphertext in ballot {
+            ciphertext.encode(encoder);
+        }
+    }
+}
+
+fn encode_points(encoder: &mut CanonicalEncoder, points: &[RistrettoPoint]) {
+    encoder.count(points.len());
+    for point in points {
+        encoder.bytes(point.compress().as_bytes());
+    }
+}
+
+/// The statement, bound to the permutation commitment; every challenge starts from it.
+fn statement(
+    election_key: &RistrettoPoint,
+    inputs: &[EncryptedBallot],
+    outputs: &[EncryptedBallot],
+    commitments: &[RistrettoPoint],
+) -> Vec<u8> {
+    let mut transcript = CanonicalEncoder::new(SHUFFLE_DOMAIN, SHUFFLE_VERSION);
+    transcript.bytes(election_key.compress().as_bytes());
+    encode_ballots(&mut transcript, inputs);
+    encode_ballots(&mut transcript, outputs);
+    encode_points(&mut transcript, commitments);
+    transcript.finish()
+}
+
+/// The challenge `u_i` each input is weighted by.
+fn weights(statement: &[u8], size: usize) -> Vec<Scalar> {
+    (0..size as u64)
+        .map(|index| {
+            let mut transcript = CanonicalEncoder::new(WEIGHT_DOMAIN, SHUFFLE_VERSION);
+            transcript.bytes(statement).u64(index);
+            challenge(&transcript)
+        })
+        .collect()
+}
+
+/// Commitments the prover opens with and the verifier recomputes.
+struct Commitments {
+    permutation: RistrettoPoint,
+    chain: RistrettoPoint,
+    weighted: RistrettoPoint,
+    reencryption: Vec<Ciphertext>,
+    links: Vec<RistrettoPoint>,
+}
+
+fn proof_challenge(statement: &[u8], chain: &[RistrettoPoint], commitments: &Commitments) -> Scalar {
+    let mut transcript = CanonicalEncoder::new(SHUFFLE_DOMAIN, SHUFFLE_VERSION);
+    transcript.bytes(statement);
+    encode_points(&mut transcript, chain);
+    for point in [&commitments.permutation, &commitments.chain, &commitments.weighted] {
+        transcript.bytes(point.compress().as_bytes());
+    }
+    transcript.count(commitments.reencryption.len());
+    for ciphertext in commitments.reencryption.iter() {
+        ciphertext.encode(&mut transcript);
+    }
+    encode_points(&mut transcript, &commitments.links);
+    challenge(&transcript)
+}
+
+fn width(ballots: &[EncryptedBallot]) -> usize {
+    ballots.first().map(|ballot| ballot.len()).unwrap_or(0)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShuffleProof {
+    /// Commitment to each column of the permutation matrix.
+    #[serde(with = "hex_points")]
+    pub permutation_commitments: Vec<RistrettoPoint>,
+    /// Chained commitments to the weights in their permuted order.
+    #[serde(with = "hex_points")]
+    pub chain_commitments: Vec<RistrettoPoint>,
+    #[serde(with = "hex_scalar")]
+    pub challenge: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub permutation_response: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub chain_response: Scalar,
+    #[serde(with = "hex_scalar")]
+    pub weighted_response: Scalar,
+    /// One per candidate.
+    #[serde(with = "hex_scalars")]
+    pub reencryption_responses: Vec<Scalar>,
+    /// One per ballot.
+    #[serde(with = "hex_scalars")]
+    pub link_responses: Vec<Scalar>,
+    /// One per ballot.
+    #[serde(with = "hex_scalars")]
+    pub weight_responses: Vec<Scalar>,
+}
+
+impl ShuffleProof {
+    /// Proves `outputs` came from `shuffle(election_key, inputs, permutation, randomness)`.
+    #[allow(dead_code)]
+    pub fn prove(
+        election_key: &RistrettoPoint,
+        inputs: &[EncryptedBallot],
+        outputs: &[EncryptedBallot],
+        permutation: &[usize],
+        randomness: &[Vec<Scalar>],
+    ) -> Self {
+        let size = inputs.len();
+        let candidates = width(inputs);
+        let (generators, base) = generators(size);
+
+        let mut commitment_randomness = vec![Scalar::ZERO; size];
+        let mut permutation_commitments = vec![RistrettoPoint::default(); size];
+        for (index, source) in permutation.iter().enumerate() {
+            commitment_randomness[*source] = random_scalar();
+            permutation_commitments[*source] =
+                generator() * commitment_randomness[*source] + generators[index];
+        }
+        let statement = statement(election_key, inputs, outputs, &permutation_commitments);
+        let weights = weights(&statement, size);
+        let permuted_weights: Vec<Scalar> = permutation.iter().map(|source| weights[*source]).collect();
+
+        let chain_randomness: Vec<Scalar> = (0..size).map(|_| random_scalar()).collect();
+        let mut chain_commitments = Vec::with_capacity(size);
+        let mut previous = base;
+        for (r, weight) in chain_randomness.iter().zip(permuted_weights.iter()) {
+            previous = generator() * r + previous * weight;
+            chain_commitments.push(previous);
+        }
+
+        let nonces: [Scalar; 3] = [random_scalar(), random_scalar(), random_scalar()];
+        let reencryption_nonces: Vec<Scalar> = (0..candidates).map(|_| random_scalar()).collect();
+        let link_nonces: Vec<Scalar> = (0..size).map(|_| random_scalar()).collect();
+        let weight_nonces: Vec<Scalar> = (0..size).map(|_| random_scalar()).collect();
+
+        let commitments = Commitments {
+            permutation: generator() * nonces[0],
+            chain: generator() * nonces[1],
+            weighted: generator() * nonces[2]
+                + generators
+                    .iter()
+                    .zip(weight_nonces.iter())
+                    .map(|(h, w)| h * w)
+                    .sum::<RistrettoPoint>(),
+            reencryption: (0..candidates)
+                .map(|column| Ciphertext {
+                    c1: outputs
+                        .iter()
+                        .zip(weight_nonces.iter())
+                        .map(|(ballot, w)| ballot[column].c1 * w)
+                        .sum::<RistrettoPoint>()
+                        - generator() * reencryption_nonces[column],
+                    c2: outputs
+                        .iter()
+                        .zip(weight_nonces.iter())
+                        .map(|(ballot, w)| ballot[column].c2 * w)
+                        .sum::<RistrettoPoint>()
+                        - election_key * reencryption_nonces[column],
+                })
+                .collect(),
+            links: (0..size)
+                .map(|index| {
+                    let previous = if index == 0 { base } else { chain_commitments[index - 1] };
+                    generator() * link_nonces[index] + previous * weight_nonces[index]
+                })
+                .collect(),
+        };
+        let challenge = proof_challenge(&statement, &chain_commitments, &commitments);
+
+        // products of the permuted weights after each position
+        let mut tail_products = vec![Scalar::ONE; size];
+        for index in (1..size).rev() {
+            tail_products[index - 1] = permuted_weights[index] * tail_products[index];
+        }
+        let chain_secret: Scalar = chain_randomness
+            .iter()
+            .zip(tail_products.iter())
+            .map(|(r, v)| r * v)
+            .sum();
+        let weighted_secret: Scalar = commitment_randomness
+            .iter()
+            .zip(weights.iter())
+            .map(|(r, u)| r * u)
+            .sum();
+
+        ShuffleProof {
+            permutation_response: nonces[0] - challenge * commitment_randomness.iter().sum::<Scalar>(),
+            chain_response: nonces[1] - challenge * chain_secret,
+            weighted_response: nonces[2] - challenge * weighted_secret,
+            reencryption_responses: (0..candidates)
+                .map(|column| {
+                    let secret: Scalar = randomness
+                        .iter()
+                        .zip(permuted_weights.iter())
+                        .map(|(r, u)| r[column] * u)
+                        .sum();
+                    reencryption_nonces[column] - challenge * secret
+                })
+                .collect(),
+            link_responses: link_nonces
+                .iter()
+                .zip(chain_randomness.iter())
+                .map(|(nonce, r)| nonce - challenge * r)
+                .collect(),
+            weight_responses: weight_nonces
+                .iter()
+                .zip(permuted_weights.iter())
+                .map(|(nonce, u)| nonce - challenge * u)
+                .collect(),
+            permutation_commitments,
+            chain_commitments,
+            challenge,
+        }
+    }
+
+    pub fn verify(&self, election_key: &RistrettoPoint, inputs: &[EncryptedBallot], outputs: &[EncryptedBallot]) -> bool {
+        let size = inputs.len();
+        let candidates = width(inputs);
+        if outputs.len() != size
+            || inputs.iter().chain(outputs.iter()).any(|ballot| ballot.len() != candidates)
+            || self.permutation_commitments.len() != size
+            || self.chain_commitments.len() != size
+            || self.reencryption_responses.len() != candidates
+            || self.link_responses.len() != size
+            || self.weight_responses.len() != size
+        {
+            return false;
+        }
+        let (generators, base) = generators(size);
+        let statement = statement(election_key, inputs, outputs, &self.permutation_commitments);
+        let weights = weights(&statement, size);
+        let c = self.challenge;
+
+        let permutation_sum: RistrettoPoint = self.permutation_commitments.iter().sum::<RistrettoPoint>()
+            - generators.iter().sum::<RistrettoPoint>();
+        let weight_product: Scalar = weights.iter().product();
+        let chain_end = self.chain_commitments.last().copied().unwrap_or(base) - base * weight_product;
+        let weighted_commitment: RistrettoPoint = self
+            .permutation_commitments
+            .iter()
+            .zip(weights.iter())
+            .map(|(commitment, u)| commitment * u)
+            .sum();
+
+        let commitments = Commitments {
+            permutation: permutation_sum * c + generator() * self.permutation_response,
+            chain: chain_end * c + generator() * self.chain_response,
+            weighted: weighted_commitment * c
+                + generator() * self.weighted_response
+                + generators
+                    .iter()
+                    .zip(self.weight_responses.iter())
+                    .map(|(h, s)| h * s)
+                    .sum::<RistrettoPoint>(),
+            reencryption: (0..candidates)
+                .map(|column| {
+                    let weighted_input = |part: fn(&Ciphertext) -> RistrettoPoint| {
+                        inputs
+                            .iter()
+                            .zip(weights.iter())
+                            .map(|(ballot, u)| part(&ballot[column]) * u)
+                            .sum::<RistrettoPoint>()
+                    };
+                    let weighted_output = |part: fn(&Ciphertext) -> RistrettoPoint| {
+                        outputs
+                            .iter()
+                            .zip(self.weight_responses.iter())
+                            .map(|(ballot, s)| part(&ballot[column]) * s)
+                            .sum::<RistrettoPoint>()
+                    };
+                    let response = self.reencryption_responses[column];
+                    Ciphertext {
+                        c1: weighted_input(|ct| ct.c1) * c - generator() * response + weighted_output(|ct| ct.c1),
+                        c2: weighted_input(|ct| ct.c2) * c - election_key * response + weighted_output(|ct| ct.c2),
+                    }
+                })
+                .collect(),
+            links: (0..size)
+                .map(|index| {
+                    let previous = if index == 0 { base } else { self.chain_commitments[index - 1] };
+                    self.chain_commitments[index] * c
+                        + generator() * self.link_responses[index]
+                        + previous * self.weight_responses[index]
+                })
+                .collect(),
+        };
+        proof_challenge(&statement, &self.chain_commitments, &commitments) == c
+    }
+
+    pub fn encode(&self, encoder: &mut CanonicalEncoder) {
+        encode_points(encoder, &self.permutation_commitments);
+        encode_points(encoder, &self.chain_commitments);
+        for scalar in [&self.challenge, &self.permutation_response, &self.chain_response, &self.weighted_response] {
+            encoder.bytes(scalar.as_bytes());
+        }
+        for responses in [&self.reencryption_responses, &self.link_responses, &self.weight_responses] {
+            encoder.count(responses.len());
+            for scalar in responses.iter() {
+                encoder.bytes(scalar.as_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::elgamal::{discrete_log, encrypt_choice};
+
+    struct Mix {
+        key: RistrettoPoint,
+        secret: Scalar,
+        inputs: Vec<EncryptedBallot>,
+        outputs: Vec<EncryptedBallot>,
+        proof: ShuffleProof,
+    }
+
+    fn mix(choices: &[usize], candidates: usize) -> Mix {
+        let secret = random_scalar();
+        let key = generator() * secret;
+        let inputs: Vec<EncryptedBallot> = choices
+            .iter()
+            .map(|choice| encrypt_choice(&key, candidates, *choice).0)
+            .collect();
+        let permutation = random_permutation(inputs.len());
+        let randomness: Vec<Vec<Scalar>> = inputs
+            .iter()
+            .map(|_| (0..candidates).map(|_| random_scalar()).collect())
+            .collect();
+        let outputs = shuffle(&key, &inputs, &permutation, &randomness);
+        let proof = ShuffleProof::prove(&key, &inputs, &outputs, &permutation, &randomness);
+        Mix {
+            key,
+            secret,
+            inputs,
+            outputs,
+            proof,
+        }
+    }
+
+    fn choice(secret: &Scalar, ballot: &EncryptedBallot) -> usize {
+        ballot
+            .iter()
+            .position(|ct| discrete_log(&(ct.c2 - ct.c1 * secret), 1) == Some(1))
+            .unwrap()
+    }
+
+    #[test]
+    fn honest_shuffle_verifies_and_keeps_the_votes() {
+        let mix = mix(&[0, 1, 2, 1, 1], 3);
+        assert!(mix.proof.verify(&mix.key, &mix.inputs, &mix.outputs));
+
+        let mut before: Vec<usize> = mix.inputs.iter().map(|b| choice(&mix.secret, b)).collect();
+        let mut after: Vec<usize> = mix.outputs.iter().map(|b| choice(&mix.secret, b)).collect();
+        before.sort_unstable();
+        after.sort_unstable();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn replaced_ciphertext_fails() {
+        let mut mix = mix(&[0, 1, 2], 3);
+        // a fresh vote for candidate 2 in place of whatever the first output held
+        mix.outputs[0] = encrypt_choice(&mix.key, 3, 2).0;
+        assert!(!mix.proof.verify(&mix.key, &mix.inputs, &mix.outputs));
+    }
+
+    #[test]
+    fn single_reencrypted_ciphertext_swapped_in_fails() {
+        let mut mix = mix(&[0, 1, 2], 3);
+        mix.outputs[1][0] = reencrypt(&mix.key, &mix.outputs[1][0], &random_scalar());
+        assert!(!mix.proof.verify(&mix.key, &mix.inputs, &mix.outputs));
+    }
+
+    #[test]
+    fn dropped_ballot_fails() {
+        let mut dropped = mix(&[0, 1, 2], 3);
+        dropped.outputs.pop();
+        assert!(!dropped.proof.verify(&dropped.key, &dropped.inputs, &dropped.outputs));
+
+        // copying one ballot over another keeps the count but drops a vote
+        let mut copied = mix(&[0, 1, 2], 3);
+        copied.outputs[2] = copied.outputs[0].clone();
+        assert!(!copied.proof.verify(&copied.key, &copied.inputs, &copied.outputs));
+    }
+}
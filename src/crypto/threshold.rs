@@ -0,0 +1,237 @@
+//! Threshold key ceremony and decryption for an election's ElGamal key.
+//!
+//! Each of the `n` trustees picks a random polynomial of degree `k - 1` and
+//! publishes commitments `C_l = a_l G` to its coefficients. The election key is
+//! the sum of every trustee's `C_0`. Trustee `j` hands trustee `i` the share
+//! `f_j(i)` privately; `i` checks it against `j`'s commitments and keeps the sum
+//! of the shares it received as its secret share. Any `k` trustees can then
+//! decrypt a tally between them without the full secret ever existing.
+//!
+//! `C_0` comes with a Schnorr proof of knowledge of `a_0`, bound to the election
+//! and the trustee's position, so no trustee can pick its `C_0` after seeing the
+//! others' to cancel them out.
+
+use curve25519_dalek::{traits::Identity, RistrettoPoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::encoding::CanonicalEncoder;
+
+use super::{
+    challenge,
+    elgamal::{discrete_log, Ciphertext},
+    generator, hex_point, hex_points, hex_scalar,
+    proofs::DleqProof,
+    random_scalar,
+};
+
+const COEFFICIENT_DOMAIN: &str = "devote/trustee-coefficient";
+const COEFFICIENT_VERSION: u8 = 1;
+
+/// One trustee's secret polynomial, held by the trustee alone.
+// run by trustees on their own machines, never by the server
+#[allow(dead_code)]
+pub struct SecretPolynomial {
+    coefficients: Vec<Scalar>,
+}
+
+#[allow(dead_code)]
+impl SecretPolynomial {
+    pub fn generate(threshold: u32) -> Self {
+        SecretPolynomial {
+            coefficients: (0..threshold).map(|_| super::random_scalar()).collect(),
+        }
+    }
+
+    pub fn commitments(&self) -> Vec<RistrettoPoint> {
+        self.coefficients.iter().map(|a| generator() * a).collect()
+    }
+
+    /// Proof that whoever publishes these commitments knows `a_0`.
+    pub fn prove_constant(&self, election_id: &str, trustee: u32) -> CoefficientProof {
+        let constant = &self.coefficients[0];
+        let nonce = random_scalar();
+        let commitment = generator() * nonce;
+        let challenge = coefficient_challenge(election_id, trustee, &(generator() * constant), &commitment);
+        CoefficientProof {
+            commitment,
+            response: nonce + challenge * constant,
+        }
+    }
+
+    /// `f(recipient)`, the share handed privately to trustee `recipient`.
+    pub fn share_for(&self, recipient: u32) -> Scalar {
+        let x = Scalar::from(recipient);
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(Scalar::ZERO, |acc, coefficient| acc * x + coefficient)
+    }
+}
+
+/// Schnorr proof of knowledge of the constant term behind `C_0`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CoefficientProof {
+    #[serde(with = "hex_point")]
+    pub commitment: RistrettoPoint,
+    #[serde(with = "hex_scalar")]
+    pub response: Scalar,
+}
+
+impl CoefficientProof {
+    pub fn verify(&self, election_id: &str, trustee: u32, constant: &RistrettoPoint) -> bool {
+        let challenge = coefficient_challenge(election_id, trustee, constant, &self.commitment);
+        generator() * self.response == self.commitment + constant * challenge
+    }
+}
+
+fn coefficient_challenge(
+    election_id: &str,
+    trustee: u32,
+    constant: &RistrettoPoint,
+    commitment: &RistrettoPoint,
+) -> Scalar {
+    let mut transcript = CanonicalEncoder::new(COEFFICIENT_DOMAIN, COEFFICIENT_VERSION);
+    transcript
+        .string(election_id)
+        .u64(trustee as u64)
+        .bytes(constant.compress().as_bytes())
+        .bytes(commitment.compress().as_bytes());
+    challenge(&transcript)
+}
+
+/// `sum(C_l * x^l)`, the public image of a polynomial evaluated at `x`.
+fn evaluate_commitments(coefficients: &[RistrettoPoint], x: u32) -> RistrettoPoint {
+    let x = Scalar::from(x);
+    coefficients
+        .iter()
+        .rev()
+        .fold(RistrettoPoint::identity(), |acc, coefficient| acc * x + coefficient)
+}
+
+/// Whether `share` is the value committed to for trustee `recipient`.
+pub fn verify_share(coefficients: &[RistrettoPoint], recipient: u32, share: &Scalar) -> bool {
+    generator() * share == evaluate_commitments(coefficients, recipient)
+}
+
+/// The election key, from every trustee's commitments.
+pub fn election_key(commitments: &[Vec<RistrettoPoint>]) -> RistrettoPoint {
+    commitments
+        .iter()
+        .filter_map(|coefficients| coefficients.first())
+        .sum()
+}
+
+/// Public image of trustee `trustee`'s secret share, which its partial
+/// decryptions are checked against.
+pub fn verification_key(commitments: &[Vec<RistrettoPoint>], trustee: u32) -> RistrettoPoint {
+    commitments
+        .iter()
+        .map(|coefficients| evaluate_commitments(coefficients, trustee))
+        .sum()
+}
+
+/// A trustee's share `x_i * c1` of the decryption of each ciphertext, each with
+/// a proof that it used the secret behind its verification key.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PartialDecryption {
+    pub trustee: u32,
+    #[serde(with = "hex_points")]
+    pub shares: Vec<RistrettoPoint>,
+    pub proofs: Vec<DleqProof>,
+}
+
+impl PartialDecryption {
+    #[allow(dead_code)]
+    pub fn create(trustee: u32, secret_share: &Scalar, ciphertexts: &[Ciphertext]) -> Self {
+        let public = generator() * secret_share;
+        let shares: Vec<RistrettoPoint> = ciphertexts.iter().map(|c| c.c1 * secret_share).collect();
+        let proofs = ciphertexts
+            .iter()
+            .zip(shares.iter())
+            .map(|(c, share)| DleqProof::prove(secret_share, &c.c1, &public, share))
+            .collect();
+        PartialDecryption {
+            trustee,
+            shares,
+            proofs,
+        }
+    }
+
+    pub fn verify(&self, verification_key: &RistrettoPoint, ciphertexts: &[Ciphertext]) -> bool {
+        self.shares.len() == ciphertexts.len()
+            && self.proofs.len() == ciphertexts.len()
+            && ciphertexts
+                .iter()
+                .zip(self.shares.iter().zip(self.proofs.iter()))
+                .all(|(c, (share, proof))| proof.verify(&c.c1, verification_key, share))
+    }
+}
+
+/// Lagrange coefficient at zero for `trustee` among the decrypting `trustees`.
+fn lagrange_at_zero(trustee: u32, trustees: &[u32]) -> Scalar {
+    let i = Scalar::from(trustee);
+    trustees
+        .iter()
+        .filter(|j| **j != trustee)
+        .fold(Scalar::ONE, |acc, j| {
+            let j = Scalar::from(*j);
+            acc * j * (j - i).invert()
+        })
+}
+
+/// Decrypts each ciphertext from verified partial decryptions by at least the
+/// threshold of distinct trustees. `max` bounds each plaintext.
+pub fn combine(partials: &[PartialDecryption], ciphertexts: &[Ciphertext], max: u64) -> Option<Vec<u64>> {
+    let trustees: Vec<u32> = partials.iter().map(|p| p.trustee).collect();
+    ciphertexts
+        .iter()
+        .enumerate()
+        .map(|(index, ciphertext)| {
+            let blinding: RistrettoPoint = partials
+                .iter()
+                .map(|p| p.shares[index] * lagrange_at_zero(p.trustee, &trustees))
+                .sum();
+            discrete_log(&(ciphertext.c2 - blinding), max)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELECTION: &str = "65f0c0ffee0000000000beef";
+
+    #[test]
+    fn constant_proof_is_bound_to_election_and_trustee() {
+        let polynomial = SecretPolynomial::generate(2);
+        let constant = polynomial.commitments()[0];
+        let proof = polynomial.prove_constant(ELECTION, 1);
+
+        assert!(proof.verify(ELECTION, 1, &constant));
+        assert!(!proof.verify(ELECTION, 2, &constant));
+        assert!(!proof.verify("65f0c0ffee0000000000cafe", 1, &constant));
+    }
+
+    #[test]
+    fn rogue_key_has_no_constant_proof() {
+        let honest = SecretPolynomial::generate(2);
+        let rogue = SecretPolynomial::generate(2);
+        // C_0 chosen to cancel the honest trustee's contribution to the key
+        let cancelling = rogue.commitments()[0] - honest.commitments()[0];
+        let proof = rogue.prove_constant(ELECTION, 2);
+
+        assert!(!proof.verify(ELECTION, 2, &cancelling));
+    }
+
+    #[test]
+    fn shares_verify_against_their_dealer_only() {
+        let dealer = SecretPolynomial::generate(3);
+        let other = SecretPolynomial::generate(3);
+        let share = dealer.share_for(2);
+
+        assert!(verify_share(&dealer.commitments(), 2, &share));
+        assert!(!verify_share(&dealer.commitments(), 3, &share));
+        assert!(!verify_share(&other.commitments(), 2, &share));
+    }
+}
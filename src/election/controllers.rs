@@ -1,14 +1,16 @@
 use crate::{
     election::{
         services::{
-            begin_election, create_election, end_election, get_all, get_candidates, get_results,
-            get_status, register_election, vote,
+            audit_ballot, begin_election, cast_anonymous, cast_prepared_ballot, create_election,
+            end_election, get_all, get_audited_ballots, get_candidates, get_receipt, get_results,
+            get_ring, get_status, prepare_ballot, register_election, reveal, vote,
         },
         types::{
-            BeginElectionDTO, CreateElectionDTO, ElectionError, RegisterElectionDTO, StatusDTO,
-            VoteDTO,
+            BallotActionDTO, BeginElectionDTO, CastBallotDTO, CreateElectionDTO, ElectionError,
+            PrepareBallotDTO, RegisterElectionDTO, RevealDTO, StatusDTO, VoteDTO,
         },
     },
+    blockchain::Transaction,
     middleware::auth::{admin_validator, user_validator},
     user::{
         models::User,
@@ -53,6 +55,36 @@ async fn handle_vote(
 ) -> Result<String, ElectionError> {
     vote(client, data).await
 }
+#[post("/reveal")]
+async fn handle_reveal(
+    client: web::Data<Client>,
+    data: web::Json<RevealDTO>,
+) -> Result<String, ElectionError> {
+    reveal(client, data).await
+}
+#[post("/prepare")]
+async fn handle_prepare(
+    client: web::Data<Client>,
+    data: web::Json<PrepareBallotDTO>,
+) -> Result<String, ElectionError> {
+    prepare_ballot(client, data).await
+}
+
+#[post("/cast")]
+async fn handle_cast_prepared(
+    client: web::Data<Client>,
+    data: web::Json<CastBallotDTO>,
+) -> Result<String, ElectionError> {
+    cast_prepared_ballot(client, data).await
+}
+
+#[post("/audit")]
+async fn handle_audit(
+    client: web::Data<Client>,
+    data: web::Json<BallotActionDTO>,
+) -> Result<String, ElectionError> {
+    audit_ballot(client, data).await
+}
 //get candidates
 //get voting state in get status
 //end elections
@@ -96,6 +128,41 @@ async fn handle_get_candidates(
     get_candidates(client, id).await
 }
 
+#[post("/cast")]
+async fn handle_cast_anonymous(
+    client: web::Data<Client>,
+    data: web::Json<Transaction>,
+) -> Result<String, ElectionError> {
+    cast_anonymous(client, data).await
+}
+
+#[get("/audited/{id}")]
+async fn handle_get_audited(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, ElectionError> {
+    let id = path.into_inner();
+    get_audited_ballots(client, id).await
+}
+
+#[get("/ring/{id}")]
+async fn handle_get_ring(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, ElectionError> {
+    let id = path.into_inner();
+    get_ring(client, id).await
+}
+
+#[get("/vote/receipt/{tracker}")]
+async fn handle_get_receipt(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, ElectionError> {
+    let tracker = path.into_inner();
+    get_receipt(client, tracker).await
+}
+
 pub fn register_controllers<
     T: ServiceFactory<ServiceRequest, Config = (), Error = Error, InitError = ()>,
 >(
@@ -110,12 +177,25 @@ pub fn register_controllers<
             .service(handle_get_all)
             .wrap(HttpAuthentication::bearer(admin_validator)),
     )
+    // public, and registered ahead of the authenticated /vote scope that would otherwise catch it
+    .service(handle_get_receipt)
     .service(
         web::scope("/vote")
             .service(handle_get_status)
             .service(handle_vote)
+            .service(handle_reveal)
+            .service(handle_prepare)
+            .service(handle_cast_prepared)
+            .service(handle_audit)
             .service(handle_register)
             .service(handle_get_candidates)
             .wrap(HttpAuthentication::bearer(user_validator)),
     )
+    // unauthenticated, so an anonymous vote is not tied to the voter's session
+    .service(
+        web::scope("/ballot")
+            .service(handle_cast_anonymous)
+            .service(handle_get_ring)
+            .service(handle_get_audited),
+    )
 }
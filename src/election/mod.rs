@@ -1,4 +1,5 @@
 pub mod models;
 pub mod controllers;
 pub mod services;
+mod tally;
 mod types;
\ No newline at end of file
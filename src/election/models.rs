@@ -1,6 +1,16 @@
 
+use curve25519_dalek::Scalar;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+
+use crate::{
+    blockchain::{
+        types::{GenesisMetadata, SealingPolicy, VotingMode},
+        utils::authority_public_key,
+    },
+    crypto::{elgamal::Ciphertext, hex_scalars, proofs::BallotProof},
+};
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Election{
     pub title: String,
@@ -10,4 +20,75 @@ pub struct Election{
     pub voters: Vec<String>,
     pub status: String,
     pub blockchain: Option<String>,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub sealing_policy: SealingPolicy,
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    #[serde(default)]
+    pub voting_mode: VotingMode,
+    /// Public keys of the trustees who hold the encrypted-ballot key, in trustee order.
+    #[serde(default)]
+    pub trustees: Vec<String>,
+    /// Set once every trustee has published their key commitments.
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    /// How many trustees re-encrypt and shuffle the ballots before the tally.
+    #[serde(default)]
+    pub mix_stages: u32,
+    /// The authority key the chain was started with, recorded when the election
+    /// begins so its blocks keep validating after the server's key changes.
+    #[serde(default)]
+    pub authority: Option<String>,
+    /// Hex Edwards key ballot credentials are blind signed with, set when an
+    /// election with ballot credentials begins.
+    #[serde(default)]
+    pub credential_key: Option<String>,
+}
+
+impl Election {
+    /// What this election's genesis block commits to, once it has been started.
+    pub fn genesis_metadata(&self, election_id: &ObjectId) -> Option<GenesisMetadata> {
+        Some(GenesisMetadata {
+            election_id: election_id.to_hex(),
+            title: self.title.clone(),
+            candidates: self.candidates.clone(),
+            scope: self.scope.clone(),
+            scope_value: self.scope_value.clone(),
+            started_at: self.started_at?,
+            // elections started before the key was recorded were signed with the current one
+            authority: self.authority.clone().unwrap_or_else(authority_public_key),
+            voting_mode: self.voting_mode.clone(),
+            encryption_key: self.encryption_key.clone(),
+            credential_key: self.credential_key.clone(),
+        })
+    }
+}
+/// Maps a voter's tracker code to the transactions their vote was recorded in.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReceiptRecord {
+    pub tracker: String,
+    pub election_id: ObjectId,
+    /// Full hash of the vote's first transaction. Empty on receipts issued before
+    /// short trackers, whose tracker was that hash.
+    #[serde(default)]
+    pub transaction_hash: String,
+    pub transaction_hashes: Vec<String>,
+}
+
+/// An encrypted ballot prepared for a voter, who then either casts it or audits
+/// it. The choice and randomness are wiped once it is cast, and published once
+/// it is audited.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PreparedBallotRecord {
+    pub ballot_id: String,
+    pub election_id: ObjectId,
+    pub voter: String,
+    /// `PREPARED`, `CAST` or `AUDITED`.
+    pub status: String,
+    pub ciphertexts: Vec<Ciphertext>,
+    pub proof: BallotProof,
+    pub candidate_id: Option<String>,
+    #[serde(with = "hex_scalars")]
+    pub randomness: Vec<Scalar>,
+    pub prepared_at: i64,
+}
@@ -1,30 +1,56 @@
 use std::f64::consts::E;
 
 use actix_web::web;
-use jwt::ToBase64;
+use chrono::Utc;
+use curve25519_dalek::Scalar;
 use mongodb::{
-    bson::{doc, oid::ObjectId},
-    options::{Acknowledgment, ReadConcern, TransactionOptions, WriteConcern},
-    Client, Collection, Cursor,
+    bson::{doc, oid::ObjectId, Document},
+    Client, ClientSession, Collection,
 };
+use rand::{rngs::OsRng, RngCore};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 use crate::{
-    blockchain::{BlockChain, Transaction},
+    blockchain::{
+        services::{load_blockchain, save_blockchain, start_session, start_transaction},
+        encoding::{CanonicalEncoder, PREPARED_BALLOT_DOMAIN},
+        types::{SealingPolicy, VotingMode},
+        utils::{authority_public_key, vote_commitment},
+        Ballot, BlockChain, Transaction,
+    },
+    crypto::{
+        elgamal::{encrypt_choice, Ciphertext},
+        point_from_hex,
+        proofs::{BallotContext, BallotProof},
+        scalar_to_hex,
+    },
+    credential::utils::credential_public_key,
     election::{self, types::ResultDTO},
-    user::{models::User, utils::generate_keys},
+    trustee::services::decrypt_tally,
+    user::{
+        models::User,
+        services::{authenticate, current_key, key_is_usable, key_owner, usable_keys},
+    },
     DB_NAME,
 };
 
 use super::{
-    models::Election,
+    models::{Election, PreparedBallotRecord, ReceiptRecord},
+    tally::{instant_runoff, single_transferable_vote},
     types::{
-        BeginElectionDTO, CreateElectionDTO, ElectionError, RegisterElectionDTO, StatusDTO, VoteDTO, ElectionDTO,
+        AuditedBallotDTO, BallotActionDTO, BeginElectionDTO, CastBallotDTO, CreateElectionDTO, ElectionError, PrepareBallotDTO, PreparedBallotDTO, ReceiptDTO, ReceiptEntryDTO, RegisterElectionDTO, RevealDTO, StatusDTO, VoteDTO, ElectionDTO,
     },
 };
 
 const USER_COLLECTION: &str = "users";
 const ELECTION_COLLECTION: &str = "election";
+pub const RECEIPT_COLLECTION: &str = "receipts";
+pub const PREPARED_BALLOT_COLLECTION: &str = "prepared_ballots";
+/// Base32 without the look-alike `0`, `1`, `I` and `O`, so codes read back cleanly.
+const TRACKER_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const TRACKER_LENGTH: usize = 12;
+const TRACKER_ATTEMPTS: usize = 5;
 
 pub async fn create_election(
     client: web::Data<Client>,
@@ -40,6 +66,65 @@ pub async fn create_election(
             content: "Invalid number of candidates".into(),
         });
     }
+    match data.sealing_policy {
+        SealingPolicy::MaxTransactions { count: 0 } => {
+            return Err(ElectionError::ValidationError {
+                content: "Sealing policy must allow at least one transaction per block".into(),
+            });
+        }
+        SealingPolicy::MaxAge { seconds } if seconds <= 0 => {
+            return Err(ElectionError::ValidationError {
+                content: "Sealing policy age must be positive".into(),
+            });
+        }
+        _ => {}
+    }
+    match data.voting_mode {
+        VotingMode::Stv { seats } if seats == 0 || seats as usize >= data.candidates.len() => {
+            return Err(ElectionError::ValidationError {
+                content: "STV elections need at least one seat and more candidates than seats"
+                    .into(),
+            });
+        }
+        VotingMode::Cumulative {
+            points,
+            max_per_candidate,
+        } if max_per_candidate == 0 || max_per_candidate > points => {
+            return Err(ElectionError::ValidationError {
+                content: "Cumulative elections need a per-candidate cap between 1 and the points each voter gets".into(),
+            });
+        }
+        VotingMode::Encrypted { threshold }
+            if threshold == 0 || threshold as usize > data.trustees.len() =>
+        {
+            return Err(ElectionError::ValidationError {
+                content: "Encrypted elections need a threshold between 1 and the number of trustees".into(),
+            });
+        }
+        _ => {}
+    }
+    if data.mix_stages > 0 {
+        if !matches!(data.voting_mode, VotingMode::Encrypted { .. }) {
+            return Err(ElectionError::ValidationError {
+                content: "Only encrypted elections can be mixed".into(),
+            });
+        }
+        if data.mix_stages as usize > data.trustees.len() {
+            return Err(ElectionError::ValidationError {
+                content: "Each mix stage needs its own trustee".into(),
+            });
+        }
+    }
+    for trustee_pk in data.trustees.iter() {
+        let trustee = user_collection
+            .find_one(doc! {"public_key": &trustee_pk}, None)
+            .await;
+        if let Ok(None) | Err(_) = trustee {
+            return Err(ElectionError::ValidationError {
+                content: format!("Trustee {} is not a registered user", trustee_pk),
+            });
+        }
+    }
     for candidate_pk in data.candidates.iter() {
         let candidate = user_collection
             .find_one(
@@ -91,6 +176,14 @@ pub async fn create_election(
                 voters: data.candidates.clone(),
                 status: "PENDING".into(),
                 blockchain: None,
+                sealing_policy: data.sealing_policy.clone(),
+                started_at: None,
+                voting_mode: data.voting_mode.clone(),
+                trustees: data.trustees.clone(),
+                encryption_key: None,
+                mix_stages: data.mix_stages,
+                authority: None,
+                credential_key: None,
             },
             None,
         )
@@ -172,6 +265,11 @@ pub async fn register_election(
                 });
             } // validation complete
         }
+        if current_key(&client, pk).await.as_ref() != Some(pk) {
+            return Err(ElectionError::ValidationError {
+                content: "Key has been rotated or revoked".into(),
+            });
+        }
 
         let result = election_collection
             .update_one(
@@ -221,22 +319,95 @@ pub async fn begin_election(
         .await;
     if let Ok(Some(election)) = election {
         if election.status == "PENDING".to_string() {
-            let mut new_blockchain = BlockChain::new(Box::new(election.voters));
-            let string_blockchain = new_blockchain.serialize();
+            if let (VotingMode::Encrypted { .. }, None) =
+                (&election.voting_mode, &election.encryption_key)
+            {
+                return Err(ElectionError::ValidationError {
+                    content: "Trustees have not finished the key ceremony".into(),
+                });
+            }
+            let mut election = election;
+            let started_at = Utc::now().timestamp();
+            election.started_at = Some(started_at);
+            election.authority = Some(authority_public_key());
+            if election.voting_mode == VotingMode::BlindCredential {
+                match credential_public_key(&election_id.to_hex()) {
+                    Ok(key) => election.credential_key = Some(key),
+                    Err(e) => {
+                        return Err(ElectionError::InternalError {
+                            content: e.to_string(),
+                        })
+                    }
+                }
+            }
+            // voters who rotated since registering are on the roll with their new key,
+            // and voters whose key was revoked outright are left off
+            let mut voters: Vec<String> = vec![];
+            for voter in election.voters.iter() {
+                if let Some(key) = current_key(&client, voter).await {
+                    if !voters.contains(&key) {
+                        voters.push(key);
+                    }
+                }
+            }
+            election.voters = voters;
+
+            let genesis = election.genesis_metadata(&election_id).unwrap();
+            let mut new_blockchain = BlockChain::new(genesis, Box::new(election.voters.clone()));
+            new_blockchain.set_sealing_policy(election.sealing_policy);
+
+            let mut session = match start_transaction(&client).await {
+                Ok(session) => session,
+                Err(e) => {
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    })
+                }
+            };
+            if let Err(e) = save_blockchain(&mut session, &election_id, &mut new_blockchain).await
+            {
+                let _ = session.abort_transaction().await;
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
+            // only the call that moves the election out of PENDING keeps its genesis
             let result = election_collection
-                .update_one(
-                    doc! {"_id":&election_id},
+                .update_one_with_session(
+                    doc! {"_id": &election_id, "status": "PENDING"},
                     doc! {
                         "$set":{
                             "status": "ONGOING",
-                            "blockchain": &string_blockchain
+                            "started_at": started_at,
+                            "voters": &election.voters,
+                            "authority": &election.authority,
+                            "credential_key": &election.credential_key,
                         }
                     },
                     None,
+                    &mut session,
                 )
                 .await;
-
-            println!("{:?}", result);
+            match result {
+                Ok(result) if result.modified_count == 1 => {}
+                Ok(_) => {
+                    let _ = session.abort_transaction().await;
+                    return Err(ElectionError::ValidationError {
+                        content: "Election was started in the meantime".into(),
+                    });
+                }
+                Err(e) => {
+                    let _ = session.abort_transaction().await;
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    });
+                }
+            }
+            if let Err(e) = session.commit_transaction().await {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
 
             let value = json!({
                 "message": "Election succesfully started"
@@ -266,23 +437,75 @@ pub async fn end_election(
         )
         .await;
     if let Ok(Some(election)) = election {
-        if election.status == "ONGOING".to_string() {
-            let _ = election_collection
-                .update_one(
-                    doc! {"_id":&election_id},
+        // commit-reveal elections stop taking votes first, then take reveals until ended again
+        let next_status = match (election.status.as_str(), &election.voting_mode) {
+            ("ONGOING", VotingMode::CommitReveal) => Some("REVEAL"),
+            ("ONGOING", _) | ("REVEAL", _) => Some("ENDED"),
+            _ => None,
+        };
+        if let Some(next_status) = next_status {
+            // whatever is still pending goes into a final block so the tally sees it
+            let mut session = match start_transaction(&client).await {
+                Ok(session) => session,
+                Err(e) => {
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    })
+                }
+            };
+            let sealed = match load_blockchain(&mut session, &election_id, &election).await {
+                Ok(mut blockchain) => {
+                    blockchain.seal_pending();
+                    save_blockchain(&mut session, &election_id, &mut blockchain).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sealed {
+                let _ = session.abort_transaction().await;
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
+
+            let result = election_collection
+                .update_one_with_session(
+                    doc! {"_id": &election_id, "status": &election.status},
                     doc! {
                         "$set":{
-                            "status": "ENDED",
+                            "status": next_status,
                         }
                     },
                     None,
+                    &mut session,
                 )
                 .await;
+            match result {
+                Ok(result) if result.modified_count == 1 => {}
+                Ok(_) => {
+                    let _ = session.abort_transaction().await;
+                    return Err(ElectionError::ValidationError {
+                        content: "Election status changed in the meantime".into(),
+                    });
+                }
+                Err(e) => {
+                    let _ = session.abort_transaction().await;
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    });
+                }
+            }
+            if let Err(e) = session.commit_transaction().await {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
 
-            let value = json!({
-                "message": "Election ended started"
-            })
-            .to_string();
+            let message = if next_status == "REVEAL" {
+                "Voting closed, reveal phase started"
+            } else {
+                "Election ended started"
+            };
+            let value = json!({ "message": message }).to_string();
 
             return Ok(value);
         }
@@ -291,35 +514,74 @@ pub async fn end_election(
         content: "Error occurred trying to update election".into(),
     })
 }
+/// Ballots signed with a login key are only taken while the election is running,
+/// whatever its voting mode.
+fn check_login_vote(mode: &VotingMode, status: &str) -> Result<(), ElectionError> {
+    match mode {
+        VotingMode::BlindCredential => Err(ElectionError::ValidationError {
+            content: "Ballots in this election are cast with a credential, not a login".into(),
+        }),
+        VotingMode::Anonymous => Err(ElectionError::ValidationError {
+            content: "Ballots in this election are ring signed and cast anonymously".into(),
+        }),
+        _ if status != "ONGOING" => Err(ElectionError::ValidationError {
+            content: "Votes can only be cast while the election is ongoing".into(),
+        }),
+        _ => Ok(()),
+    }
+}
+
 pub async fn vote(
     client: web::Data<Client>,
     data: web::Json<VoteDTO>,
 ) -> Result<String, ElectionError> {
-    let mut session = (client.start_session(None).await).unwrap();
+    let mut session = match start_transaction(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
     let election_collection: Collection<Election> = session
         .client()
         .database(DB_NAME)
         .collection(ELECTION_COLLECTION);
-    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
 
-    let options = TransactionOptions::builder()
-        .read_concern(ReadConcern::majority())
-        .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
-        .build();
-    let _ = session.start_transaction(options).await;
-
-    let keys = generate_keys(&data.password, &data.reg_no); // validate:: cannot vote for yourself
-    let pk = keys.pk.to_base64().unwrap().to_string();
-    let sk = keys.sk;
+    // the voter signs on their own device, so the sender is whoever holds the key
+    let pk = match data.transactions.first() {
+        Some(transaction) => transaction.from.clone(),
+        None => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "A vote needs at least one signed transaction".into(),
+            });
+        }
+    };
+    for transaction in data.transactions.iter() {
+        if transaction.from != pk {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Every transaction in a vote must come from the same voter".into(),
+            });
+        }
+        if transaction.to == pk {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Voters cannot vote for themselves".into(),
+            });
+        }
+        if transaction.is_anonymous() || !matches!(transaction.is_valid(), Ok(true)) {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Transaction is not signed by its sender".into(),
+            });
+        }
+    }
 
-    let user = user_collection
-        .find_one(
-            doc! {
-            "public_key": &pk },
-            None,
-        )
-        .await;
-    if let Ok(None) = user {
+    // rotated keys still sign in the elections they were registered in
+    if !key_is_usable(&client, &pk).await {
+        let _ = session.abort_transaction().await;
         return Err(ElectionError::InternalError {
             content: "Invalid Credentails".into(),
         });
@@ -336,41 +598,63 @@ pub async fn vote(
         .await;
 
     if let Ok(Some(election)) = election {
-        if data.candidate_id != pk {
-            let mut blockchain = BlockChain::from_string(election.blockchain.unwrap());
-            let mut transaction = Transaction::new(pk, data.candidate_id.clone(), 1);
-            let sign_result = &transaction.sign(sk);
-            let result = blockchain.add_transaction(transaction.clone());
-            println!("{:?} {:?}", transaction, result);
-
-            if let Ok(_) = sign_result {
-                if let Ok(_) = result {
-                    let serialized = blockchain.serialize();
-                    let result = election_collection
-                        .update_one_with_session(
-                            doc! {"_id":&election_id},
-                            doc! {
-                                "$set":{
-                                    "status": "ONGOING",
-                                    "blockchain": &serialized
-                                }
-                            },
-                            None,
-                            &mut session,
-                        )
-                        .await;
-                    if let Ok(_) = result {
-                        let value = json!({
-                            "message": &"Voted successfully"
-                        })
-                        .to_string();
-                        let _ = session.commit_transaction().await;
+        if let Err(e) = check_login_vote(&election.voting_mode, &election.status) {
+            let _ = session.abort_transaction().await;
+            return Err(e);
+        }
+        // ballots are checked by the chain; plain token transfers have to go to a candidate
+        if let Some(unknown) = data
+            .transactions
+            .iter()
+            .find(|t| t.ballot.is_none() && !election.candidates.contains(&t.to))
+        {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: format!("{} is not a candidate in this election", unknown.to),
+            });
+        }
 
-                        return Ok(value);
-                    }
-                }
+        let mut blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+            Ok(blockchain) => blockchain,
+            Err(e) => {
+                let _ = session.abort_transaction().await;
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
+        };
+        let mut hashes = vec![];
+        for transaction in data.transactions.iter() {
+            hashes.push(transaction.calculate_hash());
+            if let Err(e) = blockchain.add_transaction(transaction.clone()) {
+                let _ = session.abort_transaction().await;
+                return Err(ElectionError::ValidationError {
+                    content: e.to_string(),
+                });
             }
         }
+
+        let result = save_blockchain(&mut session, &election_id, &mut blockchain).await;
+        let receipt = match result {
+            Ok(_) => issue_receipt(&mut session, &election_id, &blockchain, hashes).await,
+            Err(e) => Err(ElectionError::InternalError {
+                content: e.to_string(),
+            }),
+        };
+        if let Ok(receipt) = receipt {
+            let value = json!({
+                "message": &"Voted successfully",
+                "receipt": receipt,
+            })
+            .to_string();
+            if let Err(e) = session.commit_transaction().await {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                });
+            }
+
+            return Ok(value);
+        }
     }
     let _ = session.abort_transaction().await;
     Err(ElectionError::InternalError {
@@ -378,6 +662,755 @@ pub async fn vote(
     })
 }
 
+/// Opens a voter's commitment during the reveal phase of a commit-reveal election.
+pub async fn reveal(
+    client: web::Data<Client>,
+    data: web::Json<RevealDTO>,
+) -> Result<String, ElectionError> {
+    let mut session = match start_transaction(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let election_collection: Collection<Election> = session
+        .client()
+        .database(DB_NAME)
+        .collection(ELECTION_COLLECTION);
+
+    let transaction = data.transaction.clone();
+    let pk = transaction.from.clone();
+    let (candidate, salt) = match &transaction.ballot {
+        Some(Ballot::Reveal { candidate, salt }) => (candidate.clone(), salt.clone()),
+        _ => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Transaction does not carry a reveal".into(),
+            });
+        }
+    };
+    let election_id = match ObjectId::parse_str(&data.election_id) {
+        Ok(election_id) => election_id,
+        Err(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = election_collection
+        .find_one_with_session(doc! {"_id": election_id}, None, &mut session)
+        .await;
+    let election = match election {
+        Ok(Some(election)) if election.status == "REVEAL" => election,
+        _ => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Election is not in its reveal phase".into(),
+            });
+        }
+    };
+    let mut blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    };
+
+    // caught here rather than left to the tally, so the voter can try again
+    let opened = vote_commitment(blockchain.chain_id(), &pk, &candidate, &salt);
+    let matches = blockchain
+        .blocks()
+        .iter()
+        .flat_map(|block| block.transactions.iter())
+        .any(|transaction| {
+            transaction.from == pk
+                && transaction.ballot == Some(Ballot::Commitment {
+                    commitment: opened.clone(),
+                })
+        });
+    if !matches || !election.candidates.contains(&candidate) {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::ValidationError {
+            content: "Candidate and salt do not open this voter's commitment".into(),
+        });
+    }
+
+    if let Err(e) = blockchain.add_transaction(transaction) {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::ValidationError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = save_blockchain(&mut session, &election_id, &mut blockchain).await {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = session.commit_transaction().await {
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    Ok(json!({ "message": "Vote revealed" }).to_string())
+}
+
+/// Accepts an anonymous vote, ring signed by the voter or cast with a ballot
+/// credential, so the voter never has to say who they are.
+pub async fn cast_anonymous(
+    client: web::Data<Client>,
+    data: web::Json<Transaction>,
+) -> Result<String, ElectionError> {
+    let transaction = data.into_inner();
+    if !transaction.is_anonymous() {
+        return Err(ElectionError::ValidationError {
+            content: "Only ring signed or credentialed votes can be cast anonymously".into(),
+        });
+    }
+    let mut session = match start_transaction(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let election_collection: Collection<Election> = session
+        .client()
+        .database(DB_NAME)
+        .collection(ELECTION_COLLECTION);
+
+    let election_id = match ObjectId::parse_str(&transaction.chain_id) {
+        Ok(election_id) => election_id,
+        Err(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = election_collection
+        .find_one_with_session(doc! {"_id": election_id}, None, &mut session)
+        .await;
+    let election = match election {
+        Ok(Some(election)) if election.status == "ONGOING" => election,
+        _ => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Election is not taking votes".into(),
+            });
+        }
+    };
+    let mut blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    };
+    let hashes = vec![transaction.calculate_hash()];
+    if let Err(e) = blockchain.add_transaction(transaction) {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::ValidationError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = save_blockchain(&mut session, &election_id, &mut blockchain).await {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    let receipt = match issue_receipt(&mut session, &election_id, &blockchain, hashes).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(e);
+        }
+    };
+    if let Err(e) = session.commit_transaction().await {
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    Ok(json!({ "message": "Voted successfully", "receipt": receipt }).to_string())
+}
+
+/// Short random code a voter can look their vote up by, 60 bits so it cannot be
+/// guessed. The vote's transaction hash is kept alongside it on the receipt.
+fn tracker_code() -> String {
+    (0..TRACKER_LENGTH)
+        .map(|_| TRACKER_ALPHABET[(OsRng.next_u32() % 32) as usize] as char)
+        .collect()
+}
+
+/// Where each of `transaction_hashes` stands on `blockchain`.
+fn receipt_entries(blockchain: &BlockChain, transaction_hashes: &[String]) -> Vec<ReceiptEntryDTO> {
+    transaction_hashes
+        .iter()
+        .map(|hash| {
+            let sealed = blockchain.find_transaction(hash);
+            let pending = blockchain
+                .pending_transactions()
+                .iter()
+                .any(|transaction| transaction.calculate_hash() == *hash);
+            let status = match (&sealed, pending) {
+                (Some(_), _) => "SEALED",
+                (None, true) => "PENDING",
+                (None, false) => "MISSING",
+            };
+            ReceiptEntryDTO {
+                transaction_hash: hash.clone(),
+                status: status.into(),
+                block_height: sealed.map(|(height, _)| height),
+                block_hash: sealed.and_then(|(_, block)| block.hash.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Records a receipt for a vote's transactions, returning what the voter is given.
+async fn issue_receipt(
+    session: &mut ClientSession,
+    election_id: &ObjectId,
+    blockchain: &BlockChain,
+    transaction_hashes: Vec<String>,
+) -> Result<ReceiptDTO, ElectionError> {
+    let receipt_collection: Collection<ReceiptRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(RECEIPT_COLLECTION);
+    // a failed insert would abort the whole vote, so collisions are retried
+    // before it; the unique tracker index still catches a concurrent one
+    let mut tracker = None;
+    for _ in 0..TRACKER_ATTEMPTS {
+        let code = tracker_code();
+        match receipt_collection
+            .find_one_with_session(doc! {"tracker": &code}, None, session)
+            .await
+        {
+            Ok(None) => {
+                tracker = Some(code);
+                break;
+            }
+            Ok(Some(_)) => continue,
+            Err(e) => {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        }
+    }
+    let tracker = match tracker {
+        Some(tracker) => tracker,
+        None => {
+            return Err(ElectionError::InternalError {
+                content: "Could not issue a unique tracker code".into(),
+            })
+        }
+    };
+    let record = ReceiptRecord {
+        tracker,
+        election_id: *election_id,
+        transaction_hash: transaction_hashes[0].clone(),
+        transaction_hashes,
+    };
+    if let Err(e) = receipt_collection
+        .insert_one_with_session(&record, None, session)
+        .await
+    {
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    Ok(ReceiptDTO {
+        transactions: receipt_entries(blockchain, &record.transaction_hashes),
+        tracker: record.tracker,
+        transaction_hash: record.transaction_hash,
+        election_id: election_id.to_hex(),
+    })
+}
+
+/// Looks a receipt up by its tracker code and checks it against the current chain.
+pub async fn get_receipt(client: web::Data<Client>, tracker: String) -> Result<String, ElectionError> {
+    let receipt_collection: Collection<ReceiptRecord> =
+        client.database(DB_NAME).collection(RECEIPT_COLLECTION);
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+
+    let tracker = tracker.to_uppercase();
+    let record = match receipt_collection.find_one(doc! {"tracker": &tracker}, None).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return Err(ElectionError::ValidationError {
+                content: "No receipt with this tracker code".into(),
+            })
+        }
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let election = match election_collection
+        .find_one(doc! {"_id": record.election_id}, None)
+        .await
+    {
+        Ok(Some(election)) => election,
+        _ => {
+            return Err(ElectionError::InternalError {
+                content: "Receipt belongs to an election that no longer exists".into(),
+            })
+        }
+    };
+    let mut session = match start_session(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let blockchain = match load_blockchain(&mut session, &record.election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let value = json!(ReceiptDTO {
+        transactions: receipt_entries(&blockchain, &record.transaction_hashes),
+        tracker,
+        transaction_hash: if record.transaction_hash.is_empty() {
+            record.transaction_hashes[0].clone()
+        } else {
+            record.transaction_hash
+        },
+        election_id: record.election_id.to_hex(),
+    })
+    .to_string();
+    Ok(value)
+}
+
+/// The public keys an anonymous vote in the election is ring signed over, in ring order.
+pub async fn get_ring(client: web::Data<Client>, id: String) -> Result<String, ElectionError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let election_id = match ObjectId::parse_str(&id) {
+        Ok(election_id) => election_id,
+        Err(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = match election_collection.find_one(doc! {"_id": election_id}, None).await {
+        Ok(Some(election)) => election,
+        _ => {
+            return Err(ElectionError::ValidationError {
+                content: "Election not found".into(),
+            })
+        }
+    };
+    let mut session = match start_session(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    Ok(json!({ "ring": blockchain.voter_roll() }).to_string())
+}
+
+/// Encrypts `voter`'s vote for `candidate_id`, with a proof that it selects
+/// exactly one candidate, along with the randomness that opens it.
+fn encrypt_ballot(
+    election_id: &ObjectId,
+    election: &Election,
+    voter: &str,
+    candidate_id: &str,
+) -> Result<(Vec<Ciphertext>, BallotProof, Vec<Scalar>), ElectionError> {
+    let position = match election.candidates.iter().position(|c| c == candidate_id) {
+        Some(position) => position,
+        None => {
+            return Err(ElectionError::ValidationError {
+                content: format!("{} is not a candidate in this election", candidate_id),
+            })
+        }
+    };
+    let election_key = match election.encryption_key.as_deref().and_then(point_from_hex) {
+        Some(key) => key,
+        None => {
+            return Err(ElectionError::InternalError {
+                content: "Election has no encryption key".into(),
+            })
+        }
+    };
+    let (ciphertexts, randomness) =
+        encrypt_choice(&election_key, election.candidates.len(), position);
+    let election_id = election_id.to_hex();
+    let context = BallotContext {
+        election_id: &election_id,
+        voter,
+    };
+    let proof = BallotProof::prove(&context, &election_key, &ciphertexts, &randomness, position);
+    Ok((ciphertexts, proof, randomness))
+}
+
+/// Hex SHA-256 fingerprint of a prepared ballot's ciphertexts, which the voter
+/// holds it to whether they go on to cast or audit it.
+fn ballot_fingerprint(ciphertexts: &[Ciphertext]) -> String {
+    let mut encoder = CanonicalEncoder::new(PREPARED_BALLOT_DOMAIN, 1);
+    encoder.count(ciphertexts.len());
+    for ciphertext in ciphertexts {
+        ciphertext.encode(&mut encoder);
+    }
+    let mut hash = Sha256::new();
+    hash.update(encoder.finish());
+    hex::encode_upper(hash.finalize())
+}
+
+/// Loads an encrypted election.
+async fn find_encrypted_election(
+    client: &Client,
+    election_id: &str,
+) -> Result<(ObjectId, Election), ElectionError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let id = match ObjectId::parse_str(election_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = match election_collection.find_one(doc! {"_id": id}, None).await {
+        Ok(Some(election)) => election,
+        _ => {
+            return Err(ElectionError::ValidationError {
+                content: "Election not found".into(),
+            })
+        }
+    };
+    if !matches!(election.voting_mode, VotingMode::Encrypted { .. }) {
+        return Err(ElectionError::ValidationError {
+            content: "Only encrypted elections prepare ballots".into(),
+        });
+    }
+    Ok((id, election))
+}
+
+/// Logs the caller in and checks they are on the election's voter roll,
+/// returning the public key they are on it with.
+async fn registered_voter(
+    client: &Client,
+    election: &Election,
+    reg_no: &str,
+    password: &str,
+) -> Result<String, ElectionError> {
+    let keys = match authenticate(client, reg_no, password).await {
+        Some(user) => usable_keys(client, &user).await,
+        None => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    match keys.into_iter().find(|key| election.voters.contains(key)) {
+        Some(pk) => Ok(pk),
+        None => Err(ElectionError::ValidationError {
+            content: "Voter is not registered for this election".into(),
+        }),
+    }
+}
+
+/// A prepared ballot is cast or audited once, and never both: once audited its
+/// randomness is public, and counting it would defeat the audit.
+fn check_prepared(status: &str) -> Result<(), ElectionError> {
+    match status {
+        "PREPARED" => Ok(()),
+        status => Err(ElectionError::ValidationError {
+            content: format!("Ballot was already {}", status.to_lowercase()),
+        }),
+    }
+}
+
+/// Matches a prepared ballot only while it is still neither cast nor audited,
+/// so whichever of the two updates it first is the only one to take effect.
+fn unclaimed_ballot(election_id: &ObjectId, ballot_id: &str) -> Document {
+    doc! {"election_id": election_id, "ballot_id": ballot_id, "status": "PREPARED"}
+}
+
+/// Loads one of the caller's prepared ballots that has not been cast or audited yet.
+async fn find_prepared_ballot(
+    client: &Client,
+    election_id: &ObjectId,
+    ballot_id: &str,
+    voter: &str,
+) -> Result<PreparedBallotRecord, ElectionError> {
+    let prepared_collection: Collection<PreparedBallotRecord> =
+        client.database(DB_NAME).collection(PREPARED_BALLOT_COLLECTION);
+    let filter = doc! {"election_id": election_id, "ballot_id": ballot_id.to_uppercase(), "voter": voter};
+    match prepared_collection.find_one(filter, None).await {
+        Ok(Some(record)) => check_prepared(&record.status).map(|_| record),
+        Ok(None) => Err(ElectionError::ValidationError {
+            content: "No prepared ballot with this id".into(),
+        }),
+        Err(e) => Err(ElectionError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Encrypts a ballot for the voter to inspect before deciding to cast or audit it.
+pub async fn prepare_ballot(
+    client: web::Data<Client>,
+    data: web::Json<PrepareBallotDTO>,
+) -> Result<String, ElectionError> {
+    let prepared_collection: Collection<PreparedBallotRecord> =
+        client.database(DB_NAME).collection(PREPARED_BALLOT_COLLECTION);
+    let (election_id, election) = find_encrypted_election(&client, &data.election_id).await?;
+    let pk = registered_voter(&client, &election, &data.reg_no, &data.password).await?;
+    if election.status != "ONGOING" {
+        return Err(ElectionError::ValidationError {
+            content: "Ballots can only be prepared while the election is ongoing".into(),
+        });
+    }
+    let (ciphertexts, proof, randomness) = encrypt_ballot(&election_id, &election, &pk, &data.candidate_id)?;
+    let record = PreparedBallotRecord {
+        ballot_id: ballot_fingerprint(&ciphertexts),
+        election_id,
+        voter: pk,
+        status: "PREPARED".into(),
+        ciphertexts,
+        proof,
+        candidate_id: Some(data.candidate_id.clone()),
+        randomness,
+        prepared_at: Utc::now().timestamp(),
+    };
+    if let Err(e) = prepared_collection.insert_one(&record, None).await {
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    let value = json!(PreparedBallotDTO {
+        ballot_id: record.ballot_id,
+        ciphertexts: record.ciphertexts,
+        proof: record.proof,
+    })
+    .to_string();
+    Ok(value)
+}
+
+/// Casts a prepared ballot in the voter's signed transaction, after which
+/// nothing that opens it is kept.
+pub async fn cast_prepared_ballot(
+    client: web::Data<Client>,
+    data: web::Json<CastBallotDTO>,
+) -> Result<String, ElectionError> {
+    let (election_id, election) = find_encrypted_election(&client, &data.election_id).await?;
+    if election.status != "ONGOING" {
+        return Err(ElectionError::ValidationError {
+            content: "Ballots can only be cast while the election is ongoing".into(),
+        });
+    }
+    let transaction = data.transaction.clone();
+    let record =
+        find_prepared_ballot(&client, &election_id, &data.ballot_id, &transaction.from).await?;
+    let prepared = Ballot::Encrypted {
+        ciphertexts: record.ciphertexts.clone(),
+        proof: record.proof.clone(),
+    };
+    if transaction.ballot.as_ref() != Some(&prepared) {
+        return Err(ElectionError::ValidationError {
+            content: "Transaction does not cast this prepared ballot".into(),
+        });
+    }
+
+    let mut session = match start_transaction(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let prepared_collection: Collection<PreparedBallotRecord> = session
+        .client()
+        .database(DB_NAME)
+        .collection(PREPARED_BALLOT_COLLECTION);
+
+    let mut blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    };
+    let hashes = vec![transaction.calculate_hash()];
+    if let Err(e) = blockchain.add_transaction(transaction) {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::ValidationError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = save_blockchain(&mut session, &election_id, &mut blockchain).await {
+        let _ = session.abort_transaction().await;
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    // the ballot was read before the transaction, so an audit may have got there first
+    let wiped = prepared_collection
+        .update_one_with_session(
+            unclaimed_ballot(&election_id, &record.ballot_id),
+            doc! {"$set": {"status": "CAST", "candidate_id": null, "randomness": []}},
+            None,
+            &mut session,
+        )
+        .await;
+    match wiped {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::ValidationError {
+                content: "Ballot was cast or audited in the meantime".into(),
+            });
+        }
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    }
+    let receipt = match issue_receipt(&mut session, &election_id, &blockchain, hashes).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(e);
+        }
+    };
+    if let Err(e) = session.commit_transaction().await {
+        return Err(ElectionError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    Ok(json!({ "message": "Voted successfully", "receipt": receipt }).to_string())
+}
+
+/// Spoils a prepared ballot and publishes what opens it, so the voter can check
+/// offline that it encrypted their choice. It can never be cast afterwards.
+pub async fn audit_ballot(
+    client: web::Data<Client>,
+    data: web::Json<BallotActionDTO>,
+) -> Result<String, ElectionError> {
+    let prepared_collection: Collection<PreparedBallotRecord> =
+        client.database(DB_NAME).collection(PREPARED_BALLOT_COLLECTION);
+    let (election_id, election) = find_encrypted_election(&client, &data.election_id).await?;
+    let pk = registered_voter(&client, &election, &data.reg_no, &data.password).await?;
+    let mut record = find_prepared_ballot(&client, &election_id, &data.ballot_id, &pk).await?;
+
+    let result = prepared_collection
+        .update_one(
+            unclaimed_ballot(&election_id, &record.ballot_id),
+            doc! {"$set": {"status": "AUDITED"}},
+            None,
+        )
+        .await;
+    match result {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Ballot was cast or audited in the meantime".into(),
+            })
+        }
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    }
+    record.status = "AUDITED".into();
+    Ok(json!(audited_ballot(&election, record)).to_string())
+}
+
+fn audited_ballot(election: &Election, record: PreparedBallotRecord) -> AuditedBallotDTO {
+    AuditedBallotDTO {
+        ballot_id: record.ballot_id,
+        candidate_id: record.candidate_id,
+        encryption_key: election.encryption_key.clone(),
+        ciphertexts: record.ciphertexts,
+        randomness: record.randomness.iter().map(scalar_to_hex).collect(),
+    }
+}
+
+/// Every ballot audited in an election, for anyone to check.
+pub async fn get_audited_ballots(client: web::Data<Client>, id: String) -> Result<String, ElectionError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let prepared_collection: Collection<PreparedBallotRecord> =
+        client.database(DB_NAME).collection(PREPARED_BALLOT_COLLECTION);
+    let election_id = match ObjectId::parse_str(&id) {
+        Ok(election_id) => election_id,
+        Err(_) => {
+            return Err(ElectionError::ValidationError {
+                content: "Invalid election id".into(),
+            })
+        }
+    };
+    let election = match election_collection.find_one(doc! {"_id": election_id}, None).await {
+        Ok(Some(election)) => election,
+        _ => {
+            return Err(ElectionError::ValidationError {
+                content: "Election not found".into(),
+            })
+        }
+    };
+    let mut cursor = match prepared_collection
+        .find(doc! {"election_id": election_id, "status": "AUDITED"}, None)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            return Err(ElectionError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let mut audited: Vec<AuditedBallotDTO> = vec![];
+    while let Ok(true) = cursor.advance().await {
+        match cursor.deserialize_current() {
+            Ok(record) => audited.push(audited_ballot(&election, record)),
+            Err(e) => {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        }
+    }
+    Ok(json!({ "audited": audited }).to_string())
+}
+
 pub async fn get_all(client: web::Data<Client>) -> Result<String, ElectionError> {
     let election_collection: Collection<ElectionDTO> =
         client.database(DB_NAME).collection(ELECTION_COLLECTION);
@@ -399,6 +1432,21 @@ pub async fn get_all(client: web::Data<Client>) -> Result<String, ElectionError>
     return Ok(value);
 }
 
+/// Whether ballots can still land on the chain; commitments are revealed after voting closes.
+fn counts_votes(status: &str) -> bool {
+    matches!(status, "ONGOING" | "REVEAL")
+}
+
+/// A commitment and its reveal each spend one of a commit-reveal voter's two tokens.
+fn voter_status(mode: &VotingMode, balance: i64) -> &'static str {
+    match (mode, balance) {
+        (VotingMode::CommitReveal, 1) => "VOTED",
+        (VotingMode::CommitReveal, 0) => "REVEALED",
+        (_, 0) => "VOTED",
+        _ => "NOT_VOTED",
+    }
+}
+
 pub async fn get_status(
     client: web::Data<Client>,
     data: web::Json<StatusDTO>,
@@ -415,12 +1463,29 @@ pub async fn get_status(
         .await;
 
     if let Ok(Some(election)) = election {
-        if election.status == "ONGOING" {
-            let blockchain = BlockChain::from_string(election.blockchain.unwrap());
-            let balance = blockchain.get_address_balance(&data.public_key);
+        if counts_votes(&election.status) {
+            let mut session = match start_session(&client).await {
+                Ok(session) => session,
+                Err(e) => {
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    })
+                }
+            };
+            let blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+                Ok(blockchain) => blockchain,
+                Err(e) => {
+                    return Err(ElectionError::InternalError {
+                        content: e.to_string(),
+                    })
+                }
+            };
+            let balance = blockchain.get_available_balance(&data.public_key);
+            let nonce = blockchain.next_nonce(&data.public_key);
 
-            let status = if balance == 0 { "VOTED" } else { "NOT_VOTED" };
-            let value = json!({ "status": &status }).to_string();
+            let status = voter_status(&election.voting_mode, balance);
+            // the nonce the voter's next signed transaction has to carry
+            let value = json!({ "status": &status, "nonce": nonce }).to_string();
             return Ok(value);
         } else {
             let value = json!({ "status": &"NOT_VOTED" }).to_string();
@@ -439,7 +1504,6 @@ pub async fn get_candidates(
 ) -> Result<String, ElectionError> {
     let election_collection: Collection<Election> =
         client.database(DB_NAME).collection(ELECTION_COLLECTION);
-    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
 
     let election_id = ObjectId::parse_str(&id).unwrap();
     let election = election_collection
@@ -452,15 +1516,9 @@ pub async fn get_candidates(
     let mut candidates: Vec<User> = vec![];
 
     if let Ok(Some(election)) = election {
+        // candidates who rotated since are found through their key history
         for c in election.candidates.iter() {
-            let user = user_collection
-                .find_one(
-                    doc! {
-                    "public_key": c },
-                    None,
-                )
-                .await;
-            if let Ok(Some(user)) = user {
+            if let Some(user) = key_owner(&client, c).await {
                 candidates.push(user);
             }
         }
@@ -477,7 +1535,6 @@ pub async fn get_results(client: web::Data<Client>, id: String) -> Result<String
 
     let election_collection: Collection<Election> =
         client.database(DB_NAME).collection(ELECTION_COLLECTION);
-    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
 
     let election_id = ObjectId::parse_str(&id).unwrap();
     let election = election_collection
@@ -490,32 +1547,158 @@ pub async fn get_results(client: web::Data<Client>, id: String) -> Result<String
     let mut results: Vec<ResultDTO> = vec![];
 
     if let Ok(Some(election)) = election {
-        let bc = election.blockchain.clone().unwrap();
-        let blockchain = BlockChain::from_string(bc);
-        for c in election.candidates.iter() {
-            let user = user_collection
-                .find_one(
-                    doc! {
-                    "public_key": c },
-                    None,
-                )
-                .await;
-            if let Ok(Some(user)) = user {
-                let pk = user.public_key.clone().unwrap();
+        let mut session = match start_session(&client).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        };
+        let blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+            Ok(blockchain) => blockchain,
+            Err(e) => {
+                return Err(ElectionError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        };
+        let ballots: Vec<&Vec<String>> = blockchain
+            .ballots()
+            .into_iter()
+            .filter_map(|ballot| match ballot {
+                Ballot::Ranked { preferences } => Some(preferences),
+                _ => None,
+            })
+            .collect();
+        // encrypted totals stay hidden until enough trustees have decrypted them
+        let revealed = blockchain.revealed_choices();
+        let decrypted = match election.voting_mode {
+            VotingMode::Encrypted { .. } => {
+                match decrypt_tally(&client, &election_id, &election, &blockchain).await {
+                    Ok(totals) => Some(totals),
+                    Err(e) => {
+                        return Err(ElectionError::ValidationError {
+                            content: e.to_string(),
+                        })
+                    }
+                }
+            }
+            _ => None,
+        };
+        for (position, c) in election.candidates.iter().enumerate() {
+            // votes went to the key the candidate stood with, which they may have rotated since
+            if let Some(user) = key_owner(&client, c).await {
+                let pk = c.clone();
+                // first preferences for ranked elections; the rounds carry the rest
+                let (votes, voters) = if let Some(totals) = &decrypted {
+                    (totals[position] as i32, totals[position] as i32)
+                } else if election.voting_mode == VotingMode::CommitReveal {
+                    let count = revealed.iter().filter(|choice| ***choice == pk).count() as i32;
+                    (count, count)
+                } else if election.voting_mode.is_ranked() {
+                    let first = ballots.iter().filter(|b| b.first() == Some(&pk)).count() as i32;
+                    (first, first)
+                } else {
+                    (
+                        blockchain.get_address_balance(&pk) as i32,
+                        blockchain.voters_for(&pk) as i32,
+                    )
+                };
 
                 let result = ResultDTO {
                     name: user.name,
                     reg_no: user.reg_no,
                     public_key: pk.clone(),
-                    votes: blockchain.get_address_balance(&pk) as i32,
+                    votes,
+                    voters,
                 };
                 results.push(result);
             }
         }
-        let value = json!({ "candidates": &results }).to_string();
+        let value = match election.voting_mode {
+            VotingMode::Plurality
+            | VotingMode::Approval
+            | VotingMode::Cumulative { .. }
+            | VotingMode::Encrypted { .. }
+            | VotingMode::Anonymous
+            | VotingMode::BlindCredential
+            | VotingMode::CommitReveal => {
+                json!({ "candidates": &results }).to_string()
+            }
+            VotingMode::RankedChoice => {
+                let rounds = instant_runoff(&election.candidates, &ballots);
+                json!({
+                    "candidates": &results,
+                    "rounds": &rounds,
+                    "winner": rounds.last().and_then(|r| r.elected.clone()),
+                })
+                .to_string()
+            }
+            VotingMode::Stv { seats } => {
+                let stv = single_transferable_vote(&election.candidates, &ballots, seats);
+                json!({
+                    "candidates": &results,
+                    "seats": stv.seats,
+                    "quota": stv.quota,
+                    "elected": &stv.elected,
+                    "rounds": &stv.rounds,
+                })
+                .to_string()
+            }
+        };
         return Ok(value);
     }
    Err(ElectionError::InternalError {
         content: "Error occurred trying to update election".into(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audited_ballot_cannot_be_cast() {
+        assert!(check_prepared("PREPARED").is_ok());
+        match check_prepared("AUDITED") {
+            Err(ElectionError::ValidationError { content }) => {
+                assert_eq!(content, "Ballot was already audited")
+            }
+            _ => panic!("an audited ballot was accepted"),
+        }
+        assert!(check_prepared("CAST").is_err());
+
+        // the cast only goes through while nothing else has claimed the ballot
+        let filter = unclaimed_ballot(&ObjectId::new(), "BALLOT");
+        assert_eq!(filter.get_str("status"), Ok("PREPARED"));
+    }
+
+    #[test]
+    fn no_votes_after_the_election_ended() {
+        assert!(check_login_vote(&VotingMode::CommitReveal, "ONGOING").is_ok());
+        for mode in [VotingMode::Plurality, VotingMode::CommitReveal] {
+            assert!(check_login_vote(&mode, "ENDED").is_err());
+            assert!(check_login_vote(&mode, "REVEAL").is_err());
+        }
+    }
+
+    #[test]
+    fn revealed_voters_are_reported_in_the_reveal_phase() {
+        assert!(counts_votes("ONGOING"));
+        assert!(counts_votes("REVEAL"));
+        assert!(!counts_votes("PENDING"));
+        assert_eq!(voter_status(&VotingMode::CommitReveal, 2), "NOT_VOTED");
+        assert_eq!(voter_status(&VotingMode::CommitReveal, 1), "VOTED");
+        assert_eq!(voter_status(&VotingMode::CommitReveal, 0), "REVEALED");
+        assert_eq!(voter_status(&VotingMode::Plurality, 0), "VOTED");
+    }
+
+    #[test]
+    fn tracker_codes_are_short_and_random() {
+        let code = tracker_code();
+        assert_eq!(code.len(), TRACKER_LENGTH);
+        assert!(code.bytes().all(|c| TRACKER_ALPHABET.contains(&c)));
+        assert_ne!(code, tracker_code());
+    }
+}
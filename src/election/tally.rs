@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CandidateCount {
+    pub candidate: String,
+    pub votes: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunoffRound {
+    pub round: usize,
+    /// Votes for each candidate still in the count, in the election's candidate order.
+    pub counts: Vec<CandidateCount>,
+    /// Ballots with no continuing candidate left on them.
+    pub exhausted: i64,
+    pub eliminated: Option<String>,
+    pub elected: Option<String>,
+}
+
+impl RunoffRound {
+    pub fn votes_for(&self, candidate: &str) -> i64 {
+        self.counts
+            .iter()
+            .find(|count| count.candidate == candidate)
+            .map(|count| count.votes)
+            .unwrap_or(0)
+    }
+}
+
+/// Counts ranked ballots by instant runoff. Each round a ballot counts for its
+/// highest-ranked continuing candidate; a candidate with more than half of the
+/// non-exhausted ballots is elected, otherwise the candidate with the fewest
+/// votes is eliminated. Ties for last place go to whoever had fewer votes in the
+/// latest earlier round that separates them, and failing that to the candidate
+/// listed later in the election.
+pub fn instant_runoff(candidates: &[String], ballots: &[&Vec<String>]) -> Vec<RunoffRound> {
+    let mut continuing: Vec<String> = candidates.to_vec();
+    let mut rounds: Vec<RunoffRound> = vec![];
+
+    while !continuing.is_empty() {
+        let mut tallies: HashMap<&String, i64> = continuing.iter().map(|c| (c, 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots {
+            match ballot.iter().find(|c| continuing.contains(c)) {
+                Some(candidate) => *tallies.get_mut(candidate).unwrap() += 1,
+                None => exhausted += 1,
+            }
+        }
+        let counts: Vec<CandidateCount> = continuing
+            .iter()
+            .map(|c| CandidateCount {
+                candidate: c.clone(),
+                votes: tallies[c],
+            })
+            .collect();
+        let active: i64 = counts.iter().map(|count| count.votes).sum();
+
+        let mut round = RunoffRound {
+            round: rounds.len() + 1,
+            counts,
+            exhausted,
+            eliminated: None,
+            elected: None,
+        };
+
+        if let Some(leader) = round.counts.iter().find(|count| count.votes * 2 > active) {
+            round.elected = Some(leader.candidate.clone());
+            rounds.push(round);
+            break;
+        }
+        if continuing.len() == 1 {
+            round.elected = Some(continuing[0].clone());
+            rounds.push(round);
+            break;
+        }
+        if active == 0 {
+            // nobody has any votes to transfer, so the count cannot go further
+            rounds.push(round);
+            break;
+        }
+
+        let loser = last_place(&round, &rounds);
+        continuing.retain(|c| *c != loser);
+        round.eliminated = Some(loser);
+        rounds.push(round);
+    }
+    rounds
+}
+
+fn last_place(round: &RunoffRound, previous: &[RunoffRound]) -> String {
+    let fewest = round.counts.iter().map(|count| count.votes).min().unwrap();
+    let mut tied: Vec<&String> = round
+        .counts
+        .iter()
+        .filter(|count| count.votes == fewest)
+        .map(|count| &count.candidate)
+        .collect();
+
+    for earlier in previous.iter().rev() {
+        if tied.len() == 1 {
+            break;
+        }
+        let fewest = tied.iter().map(|c| earlier.votes_for(c)).min().unwrap();
+        tied.retain(|c| earlier.votes_for(c) == fewest);
+    }
+    tied.last().unwrap().to_string()
+}
+
+/// Fractional votes are kept to five decimal places, truncating, as under the
+/// Scottish STV rules.
+const STV_SCALE: i64 = 100_000;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StvCount {
+    pub candidate: String,
+    pub votes: f64,
+}
+
+/// Votes moving from an elected or excluded candidate to the next preference on
+/// each ballot. `to` is empty when the ballots have no continuing preference left.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StvTransfer {
+    pub from: String,
+    pub to: Option<String>,
+    pub votes: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StvRound {
+    pub round: usize,
+    /// Votes for each continuing candidate at the start of the round.
+    pub counts: Vec<StvCount>,
+    pub exhausted: f64,
+    pub elected: Vec<String>,
+    pub excluded: Option<String>,
+    pub transfers: Vec<StvTransfer>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StvResult {
+    pub seats: u32,
+    pub quota: i64,
+    /// Winners in the order they were elected.
+    pub elected: Vec<String>,
+    pub rounds: Vec<StvRound>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StvStatus {
+    Continuing,
+    Elected,
+    Excluded,
+}
+
+fn to_votes(scaled: i64) -> f64 {
+    scaled as f64 / STV_SCALE as f64
+}
+
+/// Fills `seats` seats from ranked ballots by single transferable vote.
+///
+/// The quota is the Droop quota, `floor(ballots / (seats + 1)) + 1`. Each round
+/// every candidate reaching the quota is elected and all the ballots they hold
+/// move on to the next continuing preference at a weight scaled by
+/// `surplus / total`. When nobody reaches the quota the candidate with the
+/// fewest votes is excluded and their ballots move on at their current weight,
+/// with ties broken as in [`instant_runoff`]. Once the continuing candidates
+/// only just fill the remaining seats they are all elected.
+pub fn single_transferable_vote(
+    candidates: &[String],
+    ballots: &[&Vec<String>],
+    seats: u32,
+) -> StvResult {
+    let ballots: Vec<Vec<usize>> = ballots
+        .iter()
+        .map(|ballot| {
+            ballot
+                .iter()
+                .filter_map(|p| candidates.iter().position(|c| c == p))
+                .collect::<Vec<usize>>()
+        })
+        .filter(|ballot| !ballot.is_empty())
+        .collect();
+    let quota = ballots.len() as i64 / (seats as i64 + 1) + 1;
+    let scaled_quota = quota * STV_SCALE;
+
+    let mut status = vec![StvStatus::Continuing; candidates.len()];
+    let mut weights = vec![STV_SCALE; ballots.len()];
+    let mut holders: Vec<Option<usize>> = ballots.iter().map(|ballot| Some(ballot[0])).collect();
+    let mut elected: Vec<usize> = vec![];
+    let mut history: Vec<Vec<i64>> = vec![];
+    let mut rounds: Vec<StvRound> = vec![];
+
+    while elected.len() < seats as usize {
+        let continuing: Vec<usize> = (0..candidates.len())
+            .filter(|c| status[*c] == StvStatus::Continuing)
+            .collect();
+        if continuing.is_empty() {
+            break;
+        }
+        let mut counts = vec![0; candidates.len()];
+        let mut exhausted = 0;
+        for (holder, weight) in holders.iter().zip(weights.iter()) {
+            match holder {
+                Some(c) => counts[*c] += weight,
+                None => exhausted += weight,
+            }
+        }
+
+        let mut round = StvRound {
+            round: rounds.len() + 1,
+            counts: continuing
+                .iter()
+                .map(|c| StvCount {
+                    candidate: candidates[*c].clone(),
+                    votes: to_votes(counts[*c]),
+                })
+                .collect(),
+            exhausted: to_votes(exhausted),
+            elected: vec![],
+            excluded: None,
+            transfers: vec![],
+        };
+
+        let mut reached: Vec<usize> = continuing
+            .iter()
+            .copied()
+            .filter(|c| counts[*c] >= scaled_quota)
+            .collect();
+        if elected.len() + continuing.len() <= seats as usize {
+            reached = continuing.clone();
+        }
+        // highest first; sort_by is stable so equal counts keep candidate order
+        reached.sort_by(|a, b| counts[*b].cmp(&counts[*a]));
+
+        let mut leaving: Vec<(usize, i64, i64)> = vec![];
+        if reached.is_empty() {
+            let loser = stv_last_place(&continuing, &counts, &history);
+            status[loser] = StvStatus::Excluded;
+            round.excluded = Some(candidates[loser].clone());
+            leaving.push((loser, 1, 1));
+        } else {
+            for c in reached {
+                status[c] = StvStatus::Elected;
+                elected.push(c);
+                round.elected.push(candidates[c].clone());
+                leaving.push((c, (counts[c] - scaled_quota).max(0), counts[c]));
+            }
+        }
+
+        if elected.len() < seats as usize {
+            let mut transfers: Vec<(usize, Option<usize>, i64)> = vec![];
+            for (from, surplus, total) in leaving {
+                for (index, ballot) in ballots.iter().enumerate() {
+                    if holders[index] != Some(from) {
+                        continue;
+                    }
+                    let weight = (weights[index] as i128 * surplus as i128 / total as i128) as i64;
+                    let next = ballot
+                        .iter()
+                        .copied()
+                        .find(|c| status[*c] == StvStatus::Continuing);
+                    weights[index] = weight;
+                    holders[index] = next;
+                    match transfers.iter_mut().find(|t| t.0 == from && t.1 == next) {
+                        Some(transfer) => transfer.2 += weight,
+                        None => transfers.push((from, next, weight)),
+                    }
+                }
+            }
+            round.transfers = transfers
+                .into_iter()
+                .map(|(from, to, votes)| StvTransfer {
+                    from: candidates[from].clone(),
+                    to: to.map(|c| candidates[c].clone()),
+                    votes: to_votes(votes),
+                })
+                .collect();
+        }
+
+        history.push(counts);
+        rounds.push(round);
+    }
+
+    StvResult {
+        seats,
+        quota,
+        elected: elected.into_iter().map(|c| candidates[c].clone()).collect(),
+        rounds,
+    }
+}
+
+fn stv_last_place(continuing: &[usize], counts: &[i64], history: &[Vec<i64>]) -> usize {
+    let fewest = continuing.iter().map(|c| counts[*c]).min().unwrap();
+    let mut tied: Vec<usize> = continuing
+        .iter()
+        .copied()
+        .filter(|c| counts[*c] == fewest)
+        .collect();
+
+    for earlier in history.iter().rev() {
+        if tied.len() == 1 {
+            break;
+        }
+        let fewest = tied.iter().map(|c| earlier[*c]).min().unwrap();
+        tied.retain(|c| earlier[*c] == fewest);
+    }
+    *tied.last().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn ballots(groups: &[(usize, &[&str])]) -> Vec<Vec<String>> {
+        groups
+            .iter()
+            .flat_map(|(count, ranking)| std::iter::repeat_n(names(ranking), *count))
+            .collect()
+    }
+
+    #[test]
+    fn runoff_eliminates_from_the_bottom_and_transfers() {
+        let candidates = names(&["A", "B", "C", "D"]);
+        let ballots = ballots(&[
+            (4, &["A", "B"]),
+            (3, &["B", "A"]),
+            (2, &["C", "B"]),
+            (1, &["D", "C"]),
+        ]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let rounds = instant_runoff(&candidates, &refs);
+
+        assert_eq!(rounds.len(), 3);
+        assert_eq!(rounds[0].eliminated.as_deref(), Some("D"));
+        assert_eq!(rounds[1].votes_for("C"), 3);
+        // B and C tie on 3, and C had fewer in the round before
+        assert_eq!(rounds[1].eliminated.as_deref(), Some("C"));
+        assert_eq!(rounds[2].votes_for("B"), 5);
+        assert_eq!(rounds[2].elected.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn runoff_counts_exhausted_ballots_out_of_the_majority() {
+        let candidates = names(&["A", "B", "C"]);
+        let ballots = ballots(&[(2, &["A"]), (1, &["B"]), (1, &["C"])]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let rounds = instant_runoff(&candidates, &refs);
+
+        assert_eq!(rounds[0].exhausted, 0);
+        assert_eq!(rounds[0].elected, None);
+        assert_eq!(rounds[1].exhausted, 1);
+        // 2 of the 3 ballots still counting is a majority
+        assert_eq!(rounds[1].elected.as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn runoff_breaks_unseparated_ties_by_candidate_order() {
+        let candidates = names(&["A", "B", "C"]);
+        let ballots = ballots(&[(2, &["A"]), (1, &["B"]), (1, &["C"])]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let rounds = instant_runoff(&candidates, &refs);
+        assert_eq!(rounds[0].eliminated.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn runoff_stops_when_every_ballot_is_exhausted() {
+        let candidates = names(&["A", "B"]);
+        let rounds = instant_runoff(&candidates, &[]);
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(rounds[0].elected, None);
+        assert_eq!(rounds[0].eliminated, None);
+    }
+
+    #[test]
+    fn stv_uses_the_droop_quota() {
+        let candidates = names(&["A", "B", "C"]);
+        let ballots = ballots(&[(4, &["A"]), (3, &["B"]), (3, &["C"])]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        assert_eq!(single_transferable_vote(&candidates, &refs, 2).quota, 4);
+        assert_eq!(single_transferable_vote(&candidates, &refs, 1).quota, 6);
+        assert_eq!(single_transferable_vote(&candidates, &refs, 4).quota, 3);
+    }
+
+    #[test]
+    fn stv_transfers_surplus_truncated_to_five_places() {
+        let candidates = names(&["A", "B", "C", "D"]);
+        let ballots = ballots(&[
+            (6, &["A", "B"]),
+            (1, &["B"]),
+            (2, &["C"]),
+            (2, &["D", "C"]),
+        ]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let result = single_transferable_vote(&candidates, &refs, 2);
+
+        assert_eq!(result.quota, 4);
+        assert_eq!(result.rounds[0].elected, names(&["A"]));
+        // a surplus of 2 over 6 ballots is 0.33333 each, and 6 of those is 1.99998
+        assert_eq!(
+            result.rounds[0].transfers,
+            vec![StvTransfer {
+                from: "A".into(),
+                to: Some("B".into()),
+                votes: 1.99998,
+            }]
+        );
+        assert_eq!(result.rounds[1].counts[0].votes, 2.99998);
+        assert_eq!(result.rounds[1].excluded.as_deref(), Some("D"));
+        assert_eq!(result.rounds[2].elected, names(&["C"]));
+        assert_eq!(result.elected, names(&["A", "C"]));
+    }
+
+    #[test]
+    fn stv_fills_every_seat() {
+        let candidates = names(&["A", "B", "C"]);
+        let ballots = ballots(&[(5, &["A"]), (1, &["B"]), (1, &["C"])]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let result = single_transferable_vote(&candidates, &refs, 2);
+
+        assert_eq!(result.quota, 3);
+        assert_eq!(result.rounds[0].transfers[0].to, None);
+        assert_eq!(result.rounds[0].transfers[0].votes, 2.0);
+        assert_eq!(result.rounds[1].excluded.as_deref(), Some("C"));
+        // B is the only continuing candidate left for the last seat
+        assert_eq!(result.rounds[2].exhausted, 3.0);
+        assert_eq!(result.rounds[2].elected, names(&["B"]));
+        assert_eq!(result.elected, names(&["A", "B"]));
+    }
+
+    #[test]
+    fn stv_elects_several_in_one_round_highest_first() {
+        let candidates = names(&["A", "B", "C"]);
+        let ballots = ballots(&[(3, &["A"]), (4, &["B"]), (1, &["C"])]);
+        let refs: Vec<&Vec<String>> = ballots.iter().collect();
+        let result = single_transferable_vote(&candidates, &refs, 2);
+
+        assert_eq!(result.quota, 3);
+        assert_eq!(result.rounds.len(), 1);
+        assert_eq!(result.rounds[0].elected, names(&["B", "A"]));
+        assert_eq!(result.elected, names(&["B", "A"]));
+    }
+}
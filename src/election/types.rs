@@ -7,6 +7,14 @@ use derive_more::{Display, Error};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    blockchain::{
+        types::{SealingPolicy, VotingMode},
+        Transaction,
+    },
+    crypto::{elgamal::Ciphertext, proofs::BallotProof},
+};
+
 #[derive(Debug, Display, Error)]
 pub enum ElectionError {
     #[display(fmt = "{}", content)]
@@ -36,6 +44,16 @@ pub struct CreateElectionDTO {
     pub scope: String,
     pub scope_value: String,
     pub candidates: Vec<String>,
+    #[serde(default)]
+    pub sealing_policy: SealingPolicy,
+    #[serde(default)]
+    pub voting_mode: VotingMode,
+    /// Public keys of the trustees for an encrypted election.
+    #[serde(default)]
+    pub trustees: Vec<String>,
+    /// Trustees who must mix the ballots after voting closes, 0 for no mix.
+    #[serde(default)]
+    pub mix_stages: u32,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -49,12 +67,19 @@ pub struct BeginElectionDTO {
 
 }
 
+/// A vote built and signed on the voter's device: one transaction for most
+/// elections, one per candidate for approval and cumulative elections.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct VoteDTO {
     pub election_id: String,
-    pub password: String,
-    pub reg_no: String,
-    pub candidate_id: String,
+    pub transactions: Vec<Transaction>,
+}
+
+/// A signed transaction carrying a `REVEAL` ballot.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RevealDTO {
+    pub election_id: String,
+    pub transaction: Transaction,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -70,6 +95,8 @@ pub struct ResultDTO {
     pub reg_no: String,
     pub public_key: String,
     pub votes: i32,
+    /// Number of voters behind `votes`.
+    pub voters: i32,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -82,4 +109,80 @@ pub struct ElectionDTO{
     pub voters: Vec<String>,
     pub status: String,
     pub blockchain: Option<String>,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub sealing_policy: SealingPolicy,
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    #[serde(default)]
+    pub voting_mode: VotingMode,
+    #[serde(default)]
+    pub trustees: Vec<String>,
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    #[serde(default)]
+    pub mix_stages: u32,
+    #[serde(default)]
+    pub authority: Option<String>,
+    #[serde(default)]
+    pub credential_key: Option<String>,
+}
+/// Where one of a receipt's transactions stands on the current chain.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReceiptEntryDTO {
+    pub transaction_hash: String,
+    /// `PENDING`, `SEALED`, or `MISSING` if the chain no longer holds it.
+    pub status: String,
+    pub block_height: Option<usize>,
+    pub block_hash: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReceiptDTO {
+    pub tracker: String,
+    pub transaction_hash: String,
+    pub election_id: String,
+    pub transactions: Vec<ReceiptEntryDTO>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PrepareBallotDTO {
+    pub election_id: String,
+    pub password: String,
+    pub reg_no: String,
+    pub candidate_id: String,
+}
+
+/// Casts a prepared ballot in a transaction the voter has signed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CastBallotDTO {
+    pub election_id: String,
+    pub ballot_id: String,
+    pub transaction: Transaction,
+}
+
+/// Audits a prepared ballot.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BallotActionDTO {
+    pub election_id: String,
+    pub password: String,
+    pub reg_no: String,
+    pub ballot_id: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PreparedBallotDTO {
+    pub ballot_id: String,
+    pub ciphertexts: Vec<Ciphertext>,
+    pub proof: BallotProof,
+}
+
+/// An audited ballot with what it takes to re-encrypt it: `c1 = rG` and
+/// `c2 = mG + rY` for each candidate, `m` being 1 for `candidate_id` and 0 otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditedBallotDTO {
+    pub ballot_id: String,
+    pub candidate_id: Option<String>,
+    pub encryption_key: Option<String>,
+    pub ciphertexts: Vec<Ciphertext>,
+    pub randomness: Vec<String>,
+}
@@ -1,16 +1,37 @@
 mod blockchain;
 mod common;
+mod credential;
+mod crypto;
 mod user;
 mod middleware;
 mod election;
+mod trustee;
 
 use actix_cors::Cors;
 use actix_service::ServiceFactory;
 use actix_web::{web, App, HttpServer, dev::{ServiceRequest, ServiceResponse,}, Error};
-use common::utils::create_generic_index;
+use blockchain::{
+    models::{BlockRecord, PendingTransactionRecord},
+    services::{BLOCK_COLLECTION, PENDING_TRANSACTION_COLLECTION},
+    utils::load_authority_keys,
+};
+use common::utils::{create_compound_index, create_generic_index};
+use credential::{
+    models::{CredentialRecord, CredentialSession},
+    services::{CREDENTIAL_COLLECTION, CREDENTIAL_SESSION_COLLECTION},
+    utils::load_credential_seed,
+};
 use election::models::Election;
+use election::{
+    models::{PreparedBallotRecord, ReceiptRecord},
+    services::{PREPARED_BALLOT_COLLECTION, RECEIPT_COLLECTION},
+};
 use mongodb::Client;
-use user::models::User;
+use trustee::{models::TrusteeRecord, services::TRUSTEE_COLLECTION};
+use user::{
+    models::{KeyRecord, User},
+    services::KEY_HISTORY_COLLECTION,
+};
 
 
 const DB_NAME: &str = "devote";
@@ -18,6 +39,14 @@ const DB_NAME: &str = "devote";
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // the block signing key is checked before anything else, so a missing or
+    // malformed seed stops the server instead of sealing with a throwaway key
+    if let Err(e) = load_authority_keys() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()));
+    }
+    if let Err(e) = load_credential_seed() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()));
+    }
     let uri = std::env::var("MONGODB_URI").unwrap_or_else(|_| "".into());
     let client = Client::with_uri_str(uri)
         .await
@@ -25,7 +54,46 @@ async fn main() -> std::io::Result<()> {
 
     create_generic_index::<User>(&client, "reg_no".into(), "users".into()).await;
     create_generic_index::<User>(&client, "public_key".into(), "users".into()).await;
+    create_generic_index::<KeyRecord>(&client, "public_key".into(), KEY_HISTORY_COLLECTION.into())
+        .await;
+    create_generic_index::<ReceiptRecord>(&client, "tracker".into(), RECEIPT_COLLECTION.into()).await;
+    create_generic_index::<PreparedBallotRecord>(
+        &client,
+        "ballot_id".into(),
+        PREPARED_BALLOT_COLLECTION.into(),
+    )
+    .await;
     // create_generic_index::<Election>(&client, "".into(), "elections".into()).await;
+    create_compound_index::<BlockRecord>(
+        &client,
+        vec!["election_id".into(), "height".into()],
+        BLOCK_COLLECTION.into(),
+    )
+    .await;
+    create_compound_index::<PendingTransactionRecord>(
+        &client,
+        vec!["election_id".into(), "position".into()],
+        PENDING_TRANSACTION_COLLECTION.into(),
+    )
+    .await;
+    create_compound_index::<TrusteeRecord>(
+        &client,
+        vec!["election_id".into(), "trustee".into()],
+        TRUSTEE_COLLECTION.into(),
+    )
+    .await;
+    create_compound_index::<CredentialRecord>(
+        &client,
+        vec!["election_id".into(), "reg_no".into()],
+        CREDENTIAL_COLLECTION.into(),
+    )
+    .await;
+    create_generic_index::<CredentialSession>(
+        &client,
+        "election_id".into(),
+        CREDENTIAL_SESSION_COLLECTION.into(),
+    )
+    .await;
     
     HttpServer::new(move || {
         let cors = Cors::permissive();
@@ -33,6 +101,8 @@ async fn main() -> std::io::Result<()> {
         app = election::controllers::register_controllers(app);
         app = user::controllers::register_controllers(app);
         app = blockchain::controllers::register_controllers(app);
+        app = trustee::controllers::register_controllers(app);
+        app = credential::controllers::register_controllers(app);
         app = app.app_data(web::Data::new(client.clone()));
         app
     })
@@ -0,0 +1,97 @@
+use actix_web::{
+    dev::{ServiceFactory, ServiceRequest},
+    get, post, web, App, Error,
+};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use mongodb::Client;
+
+use crate::{
+    middleware::auth::user_validator,
+    trustee::{
+        services::{
+            acknowledge_shares, get_ceremony, get_mix, reveal_share, submit_commitments, submit_mix,
+            submit_partial_decryption,
+        },
+        types::{
+            AcknowledgementDTO, CommitmentDTO, MixDTO, PartialDecryptionDTO, RevealShareDTO,
+            TrusteeError,
+        },
+    },
+};
+
+#[post("/commit")]
+async fn handle_commit(
+    client: web::Data<Client>,
+    data: web::Json<CommitmentDTO>,
+) -> Result<String, TrusteeError> {
+    submit_commitments(client, data).await
+}
+
+#[post("/acknowledge")]
+async fn handle_acknowledge(
+    client: web::Data<Client>,
+    data: web::Json<AcknowledgementDTO>,
+) -> Result<String, TrusteeError> {
+    acknowledge_shares(client, data).await
+}
+
+#[post("/reveal")]
+async fn handle_reveal(
+    client: web::Data<Client>,
+    data: web::Json<RevealShareDTO>,
+) -> Result<String, TrusteeError> {
+    reveal_share(client, data).await
+}
+
+#[post("/decrypt")]
+async fn handle_decrypt(
+    client: web::Data<Client>,
+    data: web::Json<PartialDecryptionDTO>,
+) -> Result<String, TrusteeError> {
+    submit_partial_decryption(client, data).await
+}
+
+#[post("/mix")]
+async fn handle_mix(
+    client: web::Data<Client>,
+    data: web::Json<MixDTO>,
+) -> Result<String, TrusteeError> {
+    submit_mix(client, data).await
+}
+
+#[get("/{election_id}/mix")]
+async fn handle_get_mix(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, TrusteeError> {
+    get_mix(client, path.into_inner()).await
+}
+
+#[get("/{election_id}")]
+async fn handle_get_ceremony(
+    client: web::Data<Client>,
+    path: web::Path<String>,
+) -> Result<String, TrusteeError> {
+    get_ceremony(client, path.into_inner()).await
+}
+
+pub fn register_controllers<
+    T: ServiceFactory<ServiceRequest, Config = (), Error = Error, InitError = ()>,
+>(
+    app: App<T>,
+) -> App<T> {
+    app.service(
+        web::scope("/trustee")
+            .service(handle_commit)
+            .service(handle_acknowledge)
+            .service(handle_reveal)
+            .service(handle_decrypt)
+            .service(handle_mix)
+            .wrap(HttpAuthentication::bearer(user_validator)),
+    )
+    .service(
+        web::scope("/ceremony")
+            .service(handle_get_mix)
+            .service(handle_get_ceremony),
+    )
+}
@@ -0,0 +1,42 @@
+use curve25519_dalek::{RistrettoPoint, Scalar};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{
+    hex_points, hex_scalar,
+    threshold::{CoefficientProof, PartialDecryption},
+};
+
+/// A share a trustee dealt, revealed in public to answer a complaint about it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RevealedShare {
+    pub recipient: u32,
+    #[serde(with = "hex_scalar")]
+    pub share: Scalar,
+}
+
+/// What one trustee has published for an election: the commitments to their
+/// secret polynomial, whether the shares they were dealt check out and, once the
+/// election has ended, their partial decryption of the tally.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrusteeRecord {
+    pub election_id: ObjectId,
+    /// Position in the election's trustee list, counting from 1.
+    pub trustee: u32,
+    pub public_key: String,
+    #[serde(with = "hex_points")]
+    pub commitments: Vec<RistrettoPoint>,
+    #[serde(default)]
+    pub proof: Option<CoefficientProof>,
+    /// Set once the trustee has checked every share dealt to them.
+    #[serde(default)]
+    pub acknowledged: bool,
+    /// Trustees whose share to this one did not match their commitments.
+    #[serde(default)]
+    pub complaints: Vec<u32>,
+    /// Shares this trustee revealed to answer complaints against it.
+    #[serde(default)]
+    pub revealed: Vec<RevealedShare>,
+    #[serde(default)]
+    pub partial_decryption: Option<PartialDecryption>,
+}
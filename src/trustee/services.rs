@@ -0,0 +1,667 @@
+use actix_web::web;
+use curve25519_dalek::RistrettoPoint;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Client, Collection,
+};
+use serde_json::json;
+
+use crate::{
+    blockchain::{
+        services::{load_blockchain, save_blockchain, start_session, start_transaction},
+        types::{MixStage, VotingMode},
+        BlockChain,
+    },
+    crypto::{
+        elgamal::{sum_ballots, Ciphertext},
+        point_from_hex, point_to_hex, scalar_from_hex,
+        threshold::{combine, election_key, verification_key, verify_share, PartialDecryption},
+    },
+    election::models::Election,
+    user::services::{authenticate, usable_keys},
+    DB_NAME,
+};
+
+use super::{
+    models::{RevealedShare, TrusteeRecord},
+    types::{
+        AcknowledgementDTO, CeremonyDTO, CommitmentDTO, MixDTO, MixStatusDTO, PartialDecryptionDTO,
+        RevealShareDTO, TrusteeDTO, TrusteeError,
+    },
+};
+
+pub const TRUSTEE_COLLECTION: &str = "trustees";
+const ELECTION_COLLECTION: &str = "election";
+
+fn parse_election_id(election_id: &str) -> Result<ObjectId, TrusteeError> {
+    ObjectId::parse_str(election_id).map_err(|_| TrusteeError::ValidationError {
+        content: "Invalid election id".into(),
+    })
+}
+
+async fn find_election(client: &Client, id: &ObjectId) -> Result<(Election, u32), TrusteeError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    match election_collection.find_one(doc! {"_id": id}, None).await {
+        Ok(Some(election)) => match election.voting_mode {
+            VotingMode::Encrypted { threshold } => Ok((election, threshold)),
+            _ => Err(TrusteeError::ValidationError {
+                content: "Election does not use encrypted ballots".into(),
+            }),
+        },
+        Ok(None) => Err(TrusteeError::ValidationError {
+            content: "Election not found".into(),
+        }),
+        Err(e) => Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Position of the caller among the election's trustees, counting from 1.
+async fn trustee_index(
+    client: &Client,
+    election: &Election,
+    reg_no: &str,
+    password: &str,
+) -> Result<(u32, String), TrusteeError> {
+    let keys = match authenticate(client, reg_no, password).await {
+        Some(user) => usable_keys(client, &user).await,
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    match election.trustees.iter().position(|trustee| keys.contains(trustee)) {
+        Some(position) => Ok((position as u32 + 1, election.trustees[position].clone())),
+        None => Err(TrusteeError::ValidationError {
+            content: "Not a trustee of this election".into(),
+        }),
+    }
+}
+
+async fn load_records(client: &Client, id: &ObjectId) -> Result<Vec<TrusteeRecord>, TrusteeError> {
+    let trustee_collection: Collection<TrusteeRecord> =
+        client.database(DB_NAME).collection(TRUSTEE_COLLECTION);
+    let mut cursor = match trustee_collection.find(doc! {"election_id": id}, None).await {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let mut records = vec![];
+    while let Ok(true) = cursor.advance().await {
+        match cursor.deserialize_current() {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                return Err(TrusteeError::InternalError {
+                    content: e.to_string(),
+                })
+            }
+        }
+    }
+    records.sort_by_key(|record| record.trustee);
+    Ok(records)
+}
+
+fn all_commitments(records: &[TrusteeRecord]) -> Vec<Vec<RistrettoPoint>> {
+    records.iter().map(|record| record.commitments.clone()).collect()
+}
+
+/// Whether `dealer` has answered `complainant`'s complaint with a share that
+/// matches its commitments.
+fn complaint_answered(records: &[TrusteeRecord], complainant: u32, dealer: u32) -> bool {
+    records
+        .iter()
+        .find(|record| record.trustee == dealer)
+        .is_some_and(|record| {
+            record.revealed.iter().any(|revealed| {
+                revealed.recipient == complainant
+                    && verify_share(&record.commitments, complainant, &revealed.share)
+            })
+        })
+}
+
+/// The key can be published once every trustee has committed and checked their
+/// shares, and every complaint has been answered.
+fn ceremony_settled(election: &Election, records: &[TrusteeRecord]) -> bool {
+    records.len() == election.trustees.len()
+        && records.iter().all(|record| {
+            record.acknowledged
+                && record
+                    .complaints
+                    .iter()
+                    .all(|dealer| complaint_answered(records, record.trustee, *dealer))
+        })
+}
+
+/// Sets the election key if the ceremony has settled, returning it.
+async fn publish_key(
+    client: &Client,
+    election_id: &ObjectId,
+    election: &Election,
+) -> Result<Option<String>, TrusteeError> {
+    let election_collection: Collection<Election> =
+        client.database(DB_NAME).collection(ELECTION_COLLECTION);
+    let records = load_records(client, election_id).await?;
+    if !ceremony_settled(election, &records) {
+        return Ok(None);
+    }
+    let key = point_to_hex(&election_key(&all_commitments(&records)));
+    let result = election_collection
+        .update_one(
+            doc! {"_id": election_id, "status": "PENDING"},
+            doc! {"$set": {"encryption_key": &key}},
+            None,
+        )
+        .await;
+    match result {
+        Ok(_) => Ok(Some(key)),
+        Err(e) => Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+async fn replace_record(client: &Client, record: &TrusteeRecord) -> Result<(), TrusteeError> {
+    let trustee_collection: Collection<TrusteeRecord> =
+        client.database(DB_NAME).collection(TRUSTEE_COLLECTION);
+    let result = trustee_collection
+        .replace_one(
+            doc! {"election_id": &record.election_id, "trustee": record.trustee},
+            record,
+            None,
+        )
+        .await;
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+fn check_pending(election: &Election) -> Result<(), TrusteeError> {
+    if election.status != "PENDING" {
+        return Err(TrusteeError::ValidationError {
+            content: "The key ceremony is over once the election begins".into(),
+        });
+    }
+    Ok(())
+}
+
+/// The per-candidate sums of the ballots left by the last mix stage, or of the
+/// ballots as cast for an unmixed election, and how many ballots went in.
+fn encrypted_tally(election: &Election, blockchain: &BlockChain) -> (Vec<Ciphertext>, u64) {
+    let ballots = blockchain.tally_ballots();
+    let count = ballots.len() as u64;
+    (sum_ballots(ballots.into_iter(), election.candidates.len()), count)
+}
+
+/// Decryption only starts once every mix stage the election asked for is on the chain.
+fn check_mix_complete(election: &Election, blockchain: &BlockChain) -> Result<(), TrusteeError> {
+    let mixed = blockchain.mix_stages().len() as u32;
+    if mixed < election.mix_stages {
+        return Err(TrusteeError::ValidationError {
+            content: format!(
+                "Tally is waiting on the mix: {} of {} stages done",
+                mixed, election.mix_stages
+            ),
+        });
+    }
+    Ok(())
+}
+
+pub async fn submit_commitments(
+    client: web::Data<Client>,
+    data: web::Json<CommitmentDTO>,
+) -> Result<String, TrusteeError> {
+    let trustee_collection: Collection<TrusteeRecord> =
+        client.database(DB_NAME).collection(TRUSTEE_COLLECTION);
+
+    let election_id = parse_election_id(&data.election_id)?;
+    let (election, threshold) = find_election(&client, &election_id).await?;
+    let (trustee, public_key) = trustee_index(&client, &election, &data.reg_no, &data.password).await?;
+    if election.status != "PENDING" {
+        return Err(TrusteeError::ValidationError {
+            content: "Key commitments can only be published before the election begins".into(),
+        });
+    }
+    if data.commitments.len() != threshold as usize {
+        return Err(TrusteeError::ValidationError {
+            content: format!("Expected {} commitments, one per coefficient", threshold),
+        });
+    }
+    let commitments: Option<Vec<RistrettoPoint>> =
+        data.commitments.iter().map(|c| point_from_hex(c)).collect();
+    let commitments = match commitments {
+        Some(commitments) => commitments,
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Commitments must be hex encoded ristretto points".into(),
+            })
+        }
+    };
+    if !data.proof.verify(&election_id.to_hex(), trustee, &commitments[0]) {
+        return Err(TrusteeError::ValidationError {
+            content: "Proof of the constant coefficient does not verify".into(),
+        });
+    }
+
+    let records = load_records(&client, &election_id).await?;
+    if records.iter().any(|record| record.trustee == trustee) {
+        return Err(TrusteeError::ValidationError {
+            content: "Commitments were already published".into(),
+        });
+    }
+    let record = TrusteeRecord {
+        election_id,
+        trustee,
+        public_key,
+        commitments,
+        proof: Some(data.proof.clone()),
+        acknowledged: false,
+        complaints: vec![],
+        revealed: vec![],
+        partial_decryption: None,
+    };
+    if let Err(e) = trustee_collection.insert_one(&record, None).await {
+        return Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    Ok(json!({ "message": "Commitments published" }).to_string())
+}
+
+/// Records whether the shares dealt to the caller match the dealers'
+/// commitments. Only possible once every trustee has committed, and only once.
+pub async fn acknowledge_shares(
+    client: web::Data<Client>,
+    data: web::Json<AcknowledgementDTO>,
+) -> Result<String, TrusteeError> {
+    let election_id = parse_election_id(&data.election_id)?;
+    let (election, _) = find_election(&client, &election_id).await?;
+    let (trustee, _) = trustee_index(&client, &election, &data.reg_no, &data.password).await?;
+    check_pending(&election)?;
+
+    let records = load_records(&client, &election_id).await?;
+    if records.len() != election.trustees.len() {
+        return Err(TrusteeError::ValidationError {
+            content: "Shares can only be checked once every trustee has committed".into(),
+        });
+    }
+    let mut record = match records.iter().find(|record| record.trustee == trustee) {
+        Some(record) if !record.acknowledged => record.clone(),
+        Some(_) => {
+            return Err(TrusteeError::ValidationError {
+                content: "Shares were already acknowledged".into(),
+            })
+        }
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Trustee never published key commitments".into(),
+            })
+        }
+    };
+    let mut complaints = data.complaints.clone();
+    complaints.sort_unstable();
+    complaints.dedup();
+    if complaints
+        .iter()
+        .any(|dealer| *dealer == trustee || !records.iter().any(|record| record.trustee == *dealer))
+    {
+        return Err(TrusteeError::ValidationError {
+            content: "Complaints must name other trustees of this election".into(),
+        });
+    }
+
+    record.acknowledged = true;
+    record.complaints = complaints;
+    replace_record(&client, &record).await?;
+    let encryption_key = publish_key(&client, &election_id, &election).await?;
+    let value = json!({
+        "message": "Shares acknowledged",
+        "encryption_key": encryption_key,
+    })
+    .to_string();
+    Ok(value)
+}
+
+/// Answers a complaint against the caller by revealing the disputed share. The
+/// share is only recorded if it matches the caller's commitments, so a dealer
+/// who handed out a bad share holds the ceremony up until it deals a good one.
+pub async fn reveal_share(
+    client: web::Data<Client>,
+    data: web::Json<RevealShareDTO>,
+) -> Result<String, TrusteeError> {
+    let election_id = parse_election_id(&data.election_id)?;
+    let (election, _) = find_election(&client, &election_id).await?;
+    let (trustee, _) = trustee_index(&client, &election, &data.reg_no, &data.password).await?;
+    check_pending(&election)?;
+
+    let records = load_records(&client, &election_id).await?;
+    let complained = records
+        .iter()
+        .any(|record| record.trustee == data.complainant && record.complaints.contains(&trustee));
+    if !complained {
+        return Err(TrusteeError::ValidationError {
+            content: format!("Trustee {} has no complaint against this trustee", data.complainant),
+        });
+    }
+    let mut record = match records.iter().find(|record| record.trustee == trustee) {
+        Some(record) => record.clone(),
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Trustee never published key commitments".into(),
+            })
+        }
+    };
+    if complaint_answered(&records, data.complainant, trustee) {
+        return Err(TrusteeError::ValidationError {
+            content: "Complaint was already answered".into(),
+        });
+    }
+    let share = match scalar_from_hex(&data.share) {
+        Some(share) => share,
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Share must be a hex encoded scalar".into(),
+            })
+        }
+    };
+    if !verify_share(&record.commitments, data.complainant, &share) {
+        return Err(TrusteeError::ValidationError {
+            content: "Share does not match the published commitments".into(),
+        });
+    }
+
+    record.revealed.push(RevealedShare {
+        recipient: data.complainant,
+        share,
+    });
+    replace_record(&client, &record).await?;
+    let encryption_key = publish_key(&client, &election_id, &election).await?;
+    let value = json!({
+        "message": "Share revealed",
+        "encryption_key": encryption_key,
+    })
+    .to_string();
+    Ok(value)
+}
+
+pub async fn get_ceremony(client: web::Data<Client>, election_id: String) -> Result<String, TrusteeError> {
+    let id = parse_election_id(&election_id)?;
+    let (election, threshold) = find_election(&client, &id).await?;
+    let records = load_records(&client, &id).await?;
+    let complete = election.encryption_key.is_some();
+    let commitments = all_commitments(&records);
+
+    let trustees = records
+        .iter()
+        .map(|record| TrusteeDTO {
+            trustee: record.trustee,
+            public_key: record.public_key.clone(),
+            commitments: record.commitments.iter().map(point_to_hex).collect(),
+            proof: record.proof.clone(),
+            acknowledged: record.acknowledged,
+            complaints: record.complaints.clone(),
+            revealed: record.revealed.clone(),
+            verification_key: if complete {
+                Some(point_to_hex(&verification_key(&commitments, record.trustee)))
+            } else {
+                None
+            },
+            decrypted: record.partial_decryption.is_some(),
+        })
+        .collect();
+    let value = json!(CeremonyDTO {
+        election_id,
+        threshold,
+        encryption_key: election.encryption_key,
+        trustees,
+    })
+    .to_string();
+    Ok(value)
+}
+
+pub async fn submit_partial_decryption(
+    client: web::Data<Client>,
+    data: web::Json<PartialDecryptionDTO>,
+) -> Result<String, TrusteeError> {
+    let trustee_collection: Collection<TrusteeRecord> =
+        client.database(DB_NAME).collection(TRUSTEE_COLLECTION);
+
+    let election_id = parse_election_id(&data.election_id)?;
+    let (election, _) = find_election(&client, &election_id).await?;
+    let (trustee, _) = trustee_index(&client, &election, &data.reg_no, &data.password).await?;
+    if election.status != "ENDED" {
+        return Err(TrusteeError::ValidationError {
+            content: "The tally can only be decrypted once the election has ended".into(),
+        });
+    }
+    if data.partial_decryption.trustee != trustee {
+        return Err(TrusteeError::ValidationError {
+            content: format!("Partial decryption must be made as trustee {}", trustee),
+        });
+    }
+
+    let records = load_records(&client, &election_id).await?;
+    let mut record = match records.iter().find(|record| record.trustee == trustee) {
+        Some(record) if record.partial_decryption.is_none() => record.clone(),
+        Some(_) => {
+            return Err(TrusteeError::ValidationError {
+                content: "Partial decryption was already submitted".into(),
+            })
+        }
+        None => {
+            return Err(TrusteeError::ValidationError {
+                content: "Trustee never published key commitments".into(),
+            })
+        }
+    };
+
+    let mut session = match start_session(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    check_mix_complete(&election, &blockchain)?;
+    let (tally, _) = encrypted_tally(&election, &blockchain);
+    let key = verification_key(&all_commitments(&records), trustee);
+    if !data.partial_decryption.verify(&key, &tally) {
+        return Err(TrusteeError::ValidationError {
+            content: "Partial decryption proofs do not verify against the tally".into(),
+        });
+    }
+
+    record.partial_decryption = Some(data.partial_decryption.clone());
+    let result = trustee_collection
+        .replace_one(
+            doc! {"election_id": &election_id, "trustee": trustee},
+            &record,
+            None,
+        )
+        .await;
+    match result {
+        Ok(_) => Ok(json!({ "message": "Partial decryption accepted" }).to_string()),
+        Err(e) => Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Decrypts the per-candidate totals of an encrypted election, once at least
+/// the threshold of trustees have submitted partial decryptions that verify.
+pub async fn decrypt_tally(
+    client: &Client,
+    election_id: &ObjectId,
+    election: &Election,
+    blockchain: &BlockChain,
+) -> Result<Vec<u64>, TrusteeError> {
+    let threshold = match election.voting_mode {
+        VotingMode::Encrypted { threshold } => threshold as usize,
+        _ => {
+            return Err(TrusteeError::ValidationError {
+                content: "Election does not use encrypted ballots".into(),
+            })
+        }
+    };
+    check_mix_complete(election, blockchain)?;
+    let records = load_records(client, election_id).await?;
+    let commitments = all_commitments(&records);
+    let (tally, ballots) = encrypted_tally(election, blockchain);
+
+    // checked again here so a record edited behind the service's back is never used
+    let partials: Vec<PartialDecryption> = records
+        .iter()
+        .filter_map(|record| record.partial_decryption.clone())
+        .filter(|partial| partial.verify(&verification_key(&commitments, partial.trustee), &tally))
+        .collect();
+    if partials.len() < threshold {
+        return Err(TrusteeError::ValidationError {
+            content: format!(
+                "Tally is waiting on the trustees: {} of {} partial decryptions received",
+                partials.len(),
+                threshold
+            ),
+        });
+    }
+    match combine(&partials[..threshold], &tally, ballots) {
+        Some(totals) => Ok(totals),
+        None => Err(TrusteeError::InternalError {
+            content: "Tally could not be decrypted".into(),
+        }),
+    }
+}
+
+/// Re-encrypts and shuffles the ballots as the next mix stage, once voting has
+/// ended. Each trustee mixes at most once, and the stage is only recorded if its
+/// proof verifies against the output of the stage before it.
+pub async fn submit_mix(client: web::Data<Client>, data: web::Json<MixDTO>) -> Result<String, TrusteeError> {
+    let election_id = parse_election_id(&data.election_id)?;
+    let (election, _) = find_election(&client, &election_id).await?;
+    let (trustee, public_key) = trustee_index(&client, &election, &data.reg_no, &data.password).await?;
+    if election.status != "ENDED" {
+        return Err(TrusteeError::ValidationError {
+            content: "Ballots can only be mixed once the election has ended".into(),
+        });
+    }
+
+    let mut session = match start_transaction(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let mut blockchain = match load_blockchain(&mut session, &election_id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            let _ = session.abort_transaction().await;
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    };
+    let stages = blockchain.mix_stages();
+    if stages.len() as u32 >= election.mix_stages {
+        let _ = session.abort_transaction().await;
+        return Err(TrusteeError::ValidationError {
+            content: "Every mix stage is already done".into(),
+        });
+    }
+    if stages.iter().any(|stage| stage.trustee == trustee) {
+        let _ = session.abort_transaction().await;
+        return Err(TrusteeError::ValidationError {
+            content: "Ballots were already mixed by this trustee".into(),
+        });
+    }
+    let stage = MixStage {
+        stage: stages.len() as u32 + 1,
+        trustee,
+        public_key,
+        ballots: data.ballots.clone(),
+        proof: data.proof.clone(),
+    };
+    let number = stage.stage;
+
+    if let Err(e) = blockchain.add_mix_stage(stage) {
+        let _ = session.abort_transaction().await;
+        return Err(TrusteeError::ValidationError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = save_blockchain(&mut session, &election_id, &mut blockchain).await {
+        let _ = session.abort_transaction().await;
+        return Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    if let Err(e) = session.commit_transaction().await {
+        return Err(TrusteeError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    let value = json!({
+        "message": "Mix stage recorded",
+        "stage": number,
+    })
+    .to_string();
+    Ok(value)
+}
+
+pub async fn get_mix(client: web::Data<Client>, election_id: String) -> Result<String, TrusteeError> {
+    let id = parse_election_id(&election_id)?;
+    let (election, _) = find_election(&client, &id).await?;
+    let mut session = match start_session(&client).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Err(TrusteeError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let blockchain = match load_blockchain(&mut session, &id, &election).await {
+        Ok(blockchain) => blockchain,
+        Err(e) => {
+            return Err(TrusteeError::ValidationError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let mixed_by: Vec<u32> = blockchain
+        .mix_stages()
+        .iter()
+        .map(|stage| stage.trustee)
+        .collect();
+    let next_stage = if (mixed_by.len() as u32) < election.mix_stages {
+        Some(mixed_by.len() as u32 + 1)
+    } else {
+        None
+    };
+    let value = json!(MixStatusDTO {
+        election_id,
+        mix_stages: election.mix_stages,
+        mixed_by,
+        next_stage,
+        encryption_key: election.encryption_key.clone(),
+        ballots: blockchain.tally_ballots().into_iter().cloned().collect(),
+    })
+    .to_string();
+    Ok(value)
+}
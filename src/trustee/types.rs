@@ -0,0 +1,122 @@
+use actix_web::{
+    error,
+    http::{header::ContentType, StatusCode},
+    HttpResponse,
+};
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{
+    shuffle::{EncryptedBallot, ShuffleProof},
+    threshold::{CoefficientProof, PartialDecryption},
+};
+
+use super::models::RevealedShare;
+
+#[derive(Debug, Display, Error)]
+pub enum TrusteeError {
+    #[display(fmt = "{}", content)]
+    ValidationError { content: String },
+
+    #[display(fmt = "{}", content)]
+    InternalError { content: String },
+}
+
+impl error::ResponseError for TrusteeError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header(ContentType::json())
+            .body(self.to_string())
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match *self {
+            TrusteeError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            TrusteeError::InternalError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitmentDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    /// Hex points `a_l G` for each coefficient of the trustee's polynomial, constant term first.
+    pub commitments: Vec<String>,
+    /// Proof of knowledge of the constant term, made for this election and trustee position.
+    pub proof: CoefficientProof,
+}
+
+/// A trustee's verdict on the shares dealt to them, once every trustee has committed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AcknowledgementDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    /// Trustees whose share did not verify against their commitments; empty if all did.
+    pub complaints: Vec<u32>,
+}
+
+/// A dealer's answer to a complaint: the disputed share, in the clear.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RevealShareDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    pub complainant: u32,
+    /// Hex scalar `f(complainant)`.
+    pub share: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PartialDecryptionDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    pub partial_decryption: PartialDecryption,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MixDTO {
+    pub election_id: String,
+    pub reg_no: String,
+    pub password: String,
+    /// The re-encrypted ballots, in shuffled order.
+    pub ballots: Vec<EncryptedBallot>,
+    pub proof: ShuffleProof,
+}
+
+/// Where an election's mix stands, and the ballots the next stage has to shuffle.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MixStatusDTO {
+    pub election_id: String,
+    pub mix_stages: u32,
+    /// Trustees who have mixed so far, in stage order.
+    pub mixed_by: Vec<u32>,
+    /// `None` once every stage is done.
+    pub next_stage: Option<u32>,
+    pub encryption_key: Option<String>,
+    pub ballots: Vec<EncryptedBallot>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TrusteeDTO {
+    pub trustee: u32,
+    pub public_key: String,
+    pub commitments: Vec<String>,
+    pub proof: Option<CoefficientProof>,
+    pub acknowledged: bool,
+    pub complaints: Vec<u32>,
+    pub revealed: Vec<RevealedShare>,
+    pub verification_key: Option<String>,
+    pub decrypted: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CeremonyDTO {
+    pub election_id: String,
+    pub threshold: u32,
+    pub encryption_key: Option<String>,
+    pub trustees: Vec<TrusteeDTO>,
+}
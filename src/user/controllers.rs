@@ -1,9 +1,15 @@
 use crate::{
-    middleware::auth::admin_validator,
+    middleware::auth::{admin_validator, user_validator},
     user::{
         models::User,
-        services::{admin_login, get_user, login, sign_up},
-        types::{AdminLoginDTO, UserError, UserLoginDTO},
+        services::{
+            admin_login, export_key, get_user, import_key, login, recover_account, revoke_key,
+            rotate_key, sign_up,
+        },
+        types::{
+            AdminLoginDTO, ExportKeyDTO, ImportKeyDTO, RecoverDTO, RevokeKeyDTO, RotateKeyDTO,
+            UserError, UserLoginDTO,
+        },
     },
 };
 use actix_web::{
@@ -22,6 +28,38 @@ async fn handle_get_user(
     get_user(client, reg_no).await
 }
 
+#[post("/revoke_key")]
+async fn handle_revoke_key(
+    client: web::Data<Client>,
+    data: web::Json<RevokeKeyDTO>,
+) -> Result<String, UserError> {
+    revoke_key(client, data).await
+}
+
+#[post("/rotate")]
+async fn handle_rotate_key(
+    client: web::Data<Client>,
+    data: web::Json<RotateKeyDTO>,
+) -> Result<String, UserError> {
+    rotate_key(client, data).await
+}
+
+#[post("/export")]
+async fn handle_export_key(
+    client: web::Data<Client>,
+    data: web::Json<ExportKeyDTO>,
+) -> Result<String, UserError> {
+    export_key(client, data).await
+}
+
+#[post("/import")]
+async fn handle_import_key(
+    client: web::Data<Client>,
+    data: web::Json<ImportKeyDTO>,
+) -> Result<String, UserError> {
+    import_key(client, data).await
+}
+
 #[post("/admin_login")]
 async fn handle_admin_login(
     client: web::Data<Client>,
@@ -38,6 +76,14 @@ async fn handle_sign_up(
     sign_up(client, data).await
 }
 
+#[post("/recover")]
+async fn handle_recover(
+    client: web::Data<Client>,
+    data: web::Json<RecoverDTO>,
+) -> Result<String, UserError> {
+    recover_account(client, data).await
+}
+
 #[post("/login")]
 async fn handle_login(
     client: web::Data<Client>,
@@ -58,11 +104,20 @@ pub fn register_controllers<
         web::scope("/user")
             .service(handle_login)
             .service(handle_sign_up)
+            .service(handle_recover)
             .service(handle_admin_login),
     )
+    .service(
+        web::scope("/key")
+            .service(handle_rotate_key)
+            .service(handle_export_key)
+            .service(handle_import_key)
+            .wrap(HttpAuthentication::bearer(user_validator)),
+    )
     .service(
         web::scope("/admin")
             .service(handle_get_user)
+            .service(handle_revoke_key)
             .wrap(HttpAuthentication::bearer(admin_validator)),
     )
 }
@@ -8,6 +8,17 @@ pub struct User {
     pub department: String,
     pub facaulty: String,
     pub public_key: Option<String>,
+    /// How the key was derived from the password, so the user can derive it
+    /// again to sign. `None` when the key was generated on their device.
+    #[serde(default)]
+    pub key_derivation: Option<KeyDerivation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyDerivation {
+    pub version: u8,
+    /// Hex encoded.
+    pub salt: String,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -15,3 +26,55 @@ pub struct Admin {
     pub username: String,
     pub password: String,
 }
+
+/// One key a user has held. Keys are never deleted, so chains whose genesis
+/// names an old key can still be checked.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyRecord {
+    pub reg_no: String,
+    pub public_key: String,
+    /// `ACTIVE`, `ROTATED` or `REVOKED`.
+    pub status: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub retired_at: Option<i64>,
+    /// The key that took over from this one.
+    #[serde(default)]
+    pub replaced_by: Option<String>,
+    /// The previous key's signature over `rotation_message`, absent for the
+    /// first key and for replacements an admin made after a revocation.
+    #[serde(default)]
+    pub rotation_signature: Option<String>,
+}
+
+/// A signing key sealed under a passphrase, for moving it between devices.
+/// The format is described in `docs/keystore.md`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Keystore {
+    pub version: u8,
+    pub public_key: String,
+    pub kdf: KeystoreKdf,
+    pub cipher: KeystoreCipher,
+    /// Hex encoded sealed seed, with the tag appended.
+    pub ciphertext: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeystoreKdf {
+    /// Only `argon2id`.
+    pub name: String,
+    /// In KiB.
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Hex encoded.
+    pub salt: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeystoreCipher {
+    /// Only `chacha20-poly1305`.
+    pub name: String,
+    /// Hex encoded.
+    pub nonce: String,
+}
@@ -1,13 +1,30 @@
 use super::{
-    models::{Admin, User},
-    types::{AdminLoginDTO, UserError, UserLoginDTO},
+    models::{Admin, KeyRecord, User},
+    types::{
+        AdminLoginDTO, ExportKeyDTO, ImportKeyDTO, RecoverDTO, RevokeKeyDTO, RotateKeyDTO,
+        UserError, UserLoginDTO,
+    },
+};
+use crate::{
+    common::utils::{from_base64, to_base64},
+    user::utils::{
+        derive_keys, import_message, legacy_keys, new_key_derivation, pk_from_string,
+        recovery_message, request_is_fresh, rotation_message, seal_keystore, seed_mnemonic,
+        server_key_derivation, signature_is_valid,
+    },
+    DB_NAME,
 };
-use crate::{user::utils::generate_keys, DB_NAME};
 use actix_web::web;
 use bcrypt;
+use chrono::Utc;
+use ed25519_compact::Signature;
 use hmac::Hmac;
 use jwt::{SignWithKey, ToBase64};
-use mongodb::{bson::doc, Client, Collection};
+use mongodb::{
+    bson::{doc, to_bson},
+    options::UpdateOptions,
+    Client, Collection,
+};
 use serde_json::json;
 use sha2::Sha256;
 use std::collections::BTreeMap;
@@ -15,15 +32,49 @@ use str;
 
 const USER_COLLECTION: &str = "users";
 const ADMIN_COLLECTION: &str = "admins";
+pub const KEY_HISTORY_COLLECTION: &str = "key_history";
+
+/// The user registered under `reg_no`, if `password` is theirs.
+pub async fn authenticate(client: &Client, reg_no: &str, password: &str) -> Option<User> {
+    let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    match collection.find_one(doc! {"reg_no": reg_no}, None).await {
+        Ok(Some(user)) if bcrypt::verify(password, &user.password).unwrap_or(false) => Some(user),
+        _ => None,
+    }
+}
 
 pub async fn sign_up(
     client: web::Data<Client>,
     data: web::Json<User>,
 ) -> Result<String, UserError> {
     let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    // the voter's key pair is made and kept on their device; only the public half is sent
+    // and its recovery phrase is shown there; a derived key's phrase is returned once, here
+    let (public_key, key_derivation, mnemonic) = match &data.public_key {
+        Some(public_key) if pk_from_string(public_key).is_some() => {
+            (public_key.clone(), None, None)
+        }
+        None if server_key_derivation() => {
+            let derivation = new_key_derivation();
+            let pair = derive_keys(&data.password, &derivation).unwrap();
+            (
+                pair.pk.to_base64().unwrap().to_string(),
+                Some(derivation),
+                Some(seed_mnemonic(&pair)),
+            )
+        }
+        _ => {
+            let value = json!({
+                "message": "public_key must be a base64 encoded ed25519 public key"
+            });
+            return Err(UserError::ValidationError {
+                content: value.to_string(),
+            });
+        }
+    };
     let user = collection
         .find_one(
-            doc! {"$or": [{"reg_no": &data.reg_no},{"email": &data.email } ] },
+            doc! {"$or": [{"reg_no": &data.reg_no},{"email": &data.email },{"public_key": &public_key } ] },
             None,
         )
         .await;
@@ -31,7 +82,7 @@ pub async fn sign_up(
     match user {
         Ok(Some(_)) => {
             let value = json!({
-                "message": "user with registration number or email already exists"
+                "message": "user with registration number, email or public key already exists"
             });
             return Err(UserError::ValidationError {
                 content: value.to_string(),
@@ -40,25 +91,27 @@ pub async fn sign_up(
         Ok(None) => {
             let hashed_password = bcrypt::hash(&data.password, 10).unwrap();
             let mut user = data.into_inner();
-            let unhashed_password = user.password;
             user.password = hashed_password;
-
-            let pair = generate_keys(&unhashed_password, &user.reg_no);
-            let pk = pair.pk.to_base64().unwrap().to_string();
-            user.public_key = Some(pk);
+            user.public_key = Some(public_key);
+            user.key_derivation = key_derivation;
 
             let result = collection.insert_one(&user, None).await;
             println!("{:?} ", result);
             match result {
                 Ok(..) => {
+                    record_key(&client, &user.reg_no, user.public_key.as_ref().unwrap(), None)
+                        .await?;
                     let key: Hmac<Sha256> = hmac::Mac::new_from_slice(b"USER_SECRETE").unwrap();
                     let mut claims: BTreeMap<String, String> = BTreeMap::new();
                     claims.insert("reg_no".into(), user.reg_no.clone());
                     let token = claims.sign_with_key(&key);
 
+                    // a server-derived key is re-derived on the device from the password and salt
                     let value = json!({
                         "message": "signup successfull",
-                        "token": token.unwrap()
+                        "token": token.unwrap(),
+                        "key_derivation": &user.key_derivation,
+                        "mnemonic": mnemonic,
                     })
                     .to_string();
 
@@ -91,6 +144,7 @@ pub async fn login(
     match user {
         Ok(Some(user)) => {
             if let Ok(true) = bcrypt::verify(&data.password, &user.password) {
+                let user = migrate_legacy_key(&client, user, &data.password).await?;
                 let key: Hmac<Sha256> = hmac::Mac::new_from_slice(b"USER_SECRETE").unwrap();
                 let mut claims: BTreeMap<String, String> = BTreeMap::new();
                 claims.insert("reg_no".into(), data.reg_no.clone());
@@ -117,6 +171,527 @@ pub async fn login(
     }
     Err(UserError::InternalError { content: "".into() })
 }
+/// Moves an account whose key was copied out of `reg_no + password` onto a
+/// salted, memory-hard derivation, as a rotation signed by the old key.
+/// Elections already running keep the old key, which the user can still derive
+/// the old way to vote in them.
+async fn migrate_legacy_key(client: &Client, mut user: User, password: &str) -> Result<User, UserError> {
+    if user.key_derivation.is_some() {
+        return Ok(user);
+    }
+    let legacy_pair = legacy_keys(password, &user.reg_no);
+    let legacy = legacy_pair.pk.to_base64().unwrap().to_string();
+    // anything else was generated on the user's device
+    if user.public_key.as_ref() != Some(&legacy) {
+        return Ok(user);
+    }
+    let derivation = new_key_derivation();
+    let public_key = derive_keys(password, &derivation)
+        .unwrap()
+        .pk
+        .to_base64()
+        .unwrap()
+        .to_string();
+    let signature = legacy_pair
+        .sk
+        .sign(rotation_message(&user.reg_no, &legacy, &public_key), None);
+
+    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let result = user_collection
+        .update_one(
+            doc! {"reg_no": &user.reg_no, "public_key": &legacy},
+            doc! {"$set": {
+                "public_key": &public_key,
+                "key_derivation": to_bson(&derivation).unwrap(),
+            }},
+            None,
+        )
+        .await;
+    if let Err(e) = result {
+        return Err(UserError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    retire_key(client, &user.reg_no, &legacy, "ROTATED", Some(&public_key)).await?;
+    record_key(client, &user.reg_no, &public_key, Some(to_base64(&signature.to_vec()))).await?;
+    user.public_key = Some(public_key);
+    user.key_derivation = Some(derivation);
+    Ok(user)
+}
+
+async fn record_key(
+    client: &Client,
+    reg_no: &str,
+    public_key: &str,
+    rotation_signature: Option<String>,
+) -> Result<(), UserError> {
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    let record = KeyRecord {
+        reg_no: reg_no.into(),
+        public_key: public_key.into(),
+        status: "ACTIVE".into(),
+        created_at: Utc::now().timestamp(),
+        retired_at: None,
+        replaced_by: None,
+        rotation_signature,
+    };
+    match key_collection.insert_one(&record, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(UserError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Marks a key `ROTATED` or `REVOKED`. Keys from before the history existed are
+/// recorded as they are retired.
+async fn retire_key(
+    client: &Client,
+    reg_no: &str,
+    public_key: &str,
+    status: &str,
+    replaced_by: Option<&String>,
+) -> Result<(), UserError> {
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    let now = Utc::now().timestamp();
+    let options = UpdateOptions::builder().upsert(true).build();
+    let result = key_collection
+        .update_one(
+            doc! {"public_key": public_key},
+            doc! {
+                "$set": {"status": status, "retired_at": now, "replaced_by": replaced_by},
+                "$setOnInsert": {"reg_no": reg_no, "created_at": now},
+            },
+            options,
+        )
+        .await;
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(UserError::InternalError {
+            content: e.to_string(),
+        }),
+    }
+}
+
+/// Whether `public_key` belongs to a user or has ever been recorded for one.
+async fn key_in_use(client: &Client, public_key: &str) -> bool {
+    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    !matches!(
+        user_collection.find_one(doc! {"public_key": public_key}, None).await,
+        Ok(None)
+    ) || !matches!(
+        key_collection.find_one(doc! {"public_key": public_key}, None).await,
+        Ok(None)
+    )
+}
+
+/// The key that stands for `public_key` now: itself while it is active, the
+/// key it was handed over to after that, and nothing once revoked outright.
+pub async fn current_key(client: &Client, public_key: &str) -> Option<String> {
+    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    let mut key = public_key.to_string();
+    // every hop moves to a newer key, and a user holds a handful at most
+    for _ in 0..64 {
+        match key_collection.find_one(doc! {"public_key": &key}, None).await {
+            Ok(Some(record)) => match record.replaced_by {
+                _ if record.status == "ACTIVE" => return Some(key),
+                Some(next) => key = next,
+                None => return None,
+            },
+            Ok(None) => {
+                return match user_collection.find_one(doc! {"public_key": &key}, None).await {
+                    Ok(Some(_)) => Some(key),
+                    _ => None,
+                }
+            }
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// The user `public_key` belongs to or once did, whatever key they hold now.
+pub async fn key_owner(client: &Client, public_key: &str) -> Option<User> {
+    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    if let Ok(Some(user)) = user_collection.find_one(doc! {"public_key": public_key}, None).await {
+        return Some(user);
+    }
+    match key_collection.find_one(doc! {"public_key": public_key}, None).await {
+        Ok(Some(record)) => user_collection
+            .find_one(doc! {"reg_no": &record.reg_no}, None)
+            .await
+            .ok()
+            .flatten(),
+        _ => None,
+    }
+}
+
+/// Whether `public_key` can still sign for its owner. Rotated keys can, in the
+/// elections they were registered in before the rotation; revoked keys cannot.
+pub async fn key_is_usable(client: &Client, public_key: &str) -> bool {
+    let user_collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    match key_collection.find_one(doc! {"public_key": public_key}, None).await {
+        Ok(Some(record)) => record.status != "REVOKED",
+        Ok(None) => matches!(
+            user_collection.find_one(doc! {"public_key": public_key}, None).await,
+            Ok(Some(_))
+        ),
+        Err(_) => false,
+    }
+}
+
+/// Every key of the user's that can still sign, current key first.
+pub async fn usable_keys(client: &Client, user: &User) -> Vec<String> {
+    let key_collection: Collection<KeyRecord> =
+        client.database(DB_NAME).collection(KEY_HISTORY_COLLECTION);
+    let mut keys = vec![];
+    if let Some(public_key) = &user.public_key {
+        if key_is_usable(client, public_key).await {
+            keys.push(public_key.clone());
+        }
+    }
+    let filter = doc! {"reg_no": &user.reg_no, "status": "ROTATED"};
+    if let Ok(mut cursor) = key_collection.find(filter, None).await {
+        while let Ok(true) = cursor.advance().await {
+            if let Ok(record) = cursor.deserialize_current() {
+                keys.push(record.public_key);
+            }
+        }
+    }
+    keys
+}
+
+/// Hands a user's signing over to a new key, signed by the key it replaces.
+pub async fn rotate_key(
+    client: web::Data<Client>,
+    data: web::Json<RotateKeyDTO>,
+) -> Result<String, UserError> {
+    let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let user = match authenticate(&client, &data.reg_no, &data.password).await {
+        Some(user) => user,
+        None => {
+            return Err(UserError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    let old_key = match &user.public_key {
+        Some(old_key) if key_is_usable(&client, old_key).await => old_key.clone(),
+        _ => {
+            return Err(UserError::ValidationError {
+                content: "Key was revoked; only an admin can issue a new one".into(),
+            })
+        }
+    };
+    let new_key = data.new_public_key.clone();
+    if pk_from_string(&new_key).is_none() {
+        return Err(UserError::ValidationError {
+            content: "new_public_key must be a base64 encoded ed25519 public key".into(),
+        });
+    }
+    let signed = match (pk_from_string(&old_key), from_base64(&data.signature)) {
+        (Some(pk), Ok(sig)) => match Signature::from_slice(&sig) {
+            Ok(sig) => pk
+                .verify(rotation_message(&user.reg_no, &old_key, &new_key), &sig)
+                .is_ok(),
+            Err(_) => false,
+        },
+        _ => false,
+    };
+    if !signed {
+        return Err(UserError::ValidationError {
+            content: "Rotation must be signed by the current key".into(),
+        });
+    }
+    if let Some(derivation) = &data.key_derivation {
+        let derived = derive_keys(&data.password, derivation)
+            .map(|pair| pair.pk.to_base64().unwrap().to_string());
+        if derived.as_ref() != Some(&new_key) {
+            return Err(UserError::ValidationError {
+                content: "new_public_key does not derive from the password and salt".into(),
+            });
+        }
+    }
+    if key_in_use(&client, &new_key).await {
+        return Err(UserError::ValidationError {
+            content: "Key is already in use".into(),
+        });
+    }
+
+    let result = collection
+        .update_one(
+            doc! {"reg_no": &user.reg_no, "public_key": &old_key},
+            doc! {"$set": {
+                "public_key": &new_key,
+                "key_derivation": to_bson(&data.key_derivation).unwrap(),
+            }},
+            None,
+        )
+        .await;
+    match result {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => {
+            return Err(UserError::ValidationError {
+                content: "Key was rotated or revoked in the meantime".into(),
+            })
+        }
+        Err(e) => {
+            return Err(UserError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    }
+    retire_key(&client, &user.reg_no, &old_key, "ROTATED", Some(&new_key)).await?;
+    record_key(&client, &user.reg_no, &new_key, Some(data.signature.clone())).await?;
+    Ok(json!({ "message": "Key rotated", "public_key": new_key }).to_string())
+}
+
+/// Revokes a user's current key so it can never sign again, for when it has
+/// leaked. The user can only get a new key through `replacement`; without one
+/// the revoked key stays on the user, marked revoked in the key history.
+pub async fn revoke_key(
+    client: web::Data<Client>,
+    data: web::Json<RevokeKeyDTO>,
+) -> Result<String, UserError> {
+    let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let user = match collection.find_one(doc! {"reg_no": &data.reg_no}, None).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(UserError::ValidationError {
+                content: "User not found".into(),
+            })
+        }
+        Err(e) => {
+            return Err(UserError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let old_key = match &user.public_key {
+        Some(old_key) => old_key.clone(),
+        None => {
+            return Err(UserError::ValidationError {
+                content: "User has no key to revoke".into(),
+            })
+        }
+    };
+    // a revoked key can still be given a replacement later
+    if data.replacement.is_none() && !key_is_usable(&client, &old_key).await {
+        return Err(UserError::ValidationError {
+            content: "Key is already revoked".into(),
+        });
+    }
+    if let Some(replacement) = &data.replacement {
+        if pk_from_string(replacement).is_none() {
+            return Err(UserError::ValidationError {
+                content: "replacement must be a base64 encoded ed25519 public key".into(),
+            });
+        }
+        if key_in_use(&client, replacement).await {
+            return Err(UserError::ValidationError {
+                content: "Key is already in use".into(),
+            });
+        }
+    }
+
+    let public_key = data.replacement.as_ref().unwrap_or(&old_key);
+    let result = collection
+        .update_one(
+            doc! {"reg_no": &user.reg_no, "public_key": &old_key},
+            doc! {"$set": {"public_key": public_key, "key_derivation": null}},
+            None,
+        )
+        .await;
+    if let Err(e) = result {
+        return Err(UserError::InternalError {
+            content: e.to_string(),
+        });
+    }
+    retire_key(&client, &user.reg_no, &old_key, "REVOKED", data.replacement.as_ref()).await?;
+    if let Some(replacement) = &data.replacement {
+        record_key(&client, &user.reg_no, replacement, None).await?;
+    }
+    Ok(json!({ "message": "Key revoked", "public_key": &data.replacement }).to_string())
+}
+
+/// Seals a server-derived key into a keystore the user can take to a client
+/// that holds its own keys. Keys made on the device never reach the server, so
+/// they are exported from the device.
+pub async fn export_key(
+    client: web::Data<Client>,
+    data: web::Json<ExportKeyDTO>,
+) -> Result<String, UserError> {
+    let user = match authenticate(&client, &data.reg_no, &data.password).await {
+        Some(user) => user,
+        None => {
+            return Err(UserError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    let pair = match &user.key_derivation {
+        Some(derivation) => derive_keys(&data.password, derivation),
+        None => {
+            return Err(UserError::ValidationError {
+                content: "Key was generated on the device; export it from there".into(),
+            })
+        }
+    };
+    let pair = match pair {
+        Some(pair) if user.public_key == pair.pk.to_base64().ok().map(|pk| pk.to_string()) => pair,
+        _ => {
+            return Err(UserError::InternalError {
+                content: "Key does not derive from the stored salt".into(),
+            })
+        }
+    };
+    if data.passphrase.is_empty() {
+        return Err(UserError::ValidationError {
+            content: "passphrase must not be empty".into(),
+        });
+    }
+    Ok(json!({ "keystore": seal_keystore(&pair, &data.passphrase) }).to_string())
+}
+
+/// Checks the user holds their current key, opened from a keystore on their
+/// device, and for a server-derived key stops deriving it: from then on the
+/// user signs with the keystore.
+pub async fn import_key(
+    client: web::Data<Client>,
+    data: web::Json<ImportKeyDTO>,
+) -> Result<String, UserError> {
+    let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let user = match authenticate(&client, &data.reg_no, &data.password).await {
+        Some(user) => user,
+        None => {
+            return Err(UserError::ValidationError {
+                content: "Invalid Credentails".into(),
+            })
+        }
+    };
+    let public_key = &data.public_key;
+    if user.public_key.as_ref() != Some(public_key) || !key_is_usable(&client, public_key).await {
+        return Err(UserError::ValidationError {
+            content: "Keystore does not hold the user's current key".into(),
+        });
+    }
+    if !request_is_fresh(data.issued_at, Utc::now().timestamp()) {
+        return Err(UserError::ValidationError {
+            content: "Import request has expired".into(),
+        });
+    }
+    let message = import_message(&user.reg_no, public_key, data.issued_at);
+    if !signature_is_valid(public_key, &message, &data.signature) {
+        return Err(UserError::ValidationError {
+            content: "Import must be signed by the key from the keystore".into(),
+        });
+    }
+    if user.key_derivation.is_some() {
+        let result = collection
+            .update_one(
+                doc! {"reg_no": &user.reg_no, "public_key": public_key},
+                doc! {"$set": {"key_derivation": null}},
+                None,
+            )
+            .await;
+        if let Err(e) = result {
+            return Err(UserError::InternalError {
+                content: e.to_string(),
+            });
+        }
+    }
+    Ok(json!({ "message": "Key imported", "public_key": public_key }).to_string())
+}
+
+/// Sets a new password for a user who has forgotten theirs, on a signature by
+/// the current key, which they restore from its recovery phrase. A derived key
+/// no longer derives from the new password, so the user holds it from then on.
+pub async fn recover_account(
+    client: web::Data<Client>,
+    data: web::Json<RecoverDTO>,
+) -> Result<String, UserError> {
+    let collection: Collection<User> = client.database(DB_NAME).collection(USER_COLLECTION);
+    let user = match collection.find_one(doc! {"reg_no": &data.reg_no}, None).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(UserError::ValidationError {
+                content: "User not found".into(),
+            })
+        }
+        Err(e) => {
+            return Err(UserError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    };
+    let public_key = match &user.public_key {
+        Some(public_key) if key_is_usable(&client, public_key).await => public_key.clone(),
+        _ => {
+            return Err(UserError::ValidationError {
+                content: "Key was revoked; only an admin can issue a new one".into(),
+            })
+        }
+    };
+    if !request_is_fresh(data.issued_at, Utc::now().timestamp()) {
+        return Err(UserError::ValidationError {
+            content: "Recovery request has expired".into(),
+        });
+    }
+    if data.new_password.is_empty() {
+        return Err(UserError::ValidationError {
+            content: "new_password must not be empty".into(),
+        });
+    }
+    let message = recovery_message(&user.reg_no, &public_key, &data.new_password, data.issued_at);
+    if !signature_is_valid(&public_key, &message, &data.signature) {
+        return Err(UserError::ValidationError {
+            content: "Recovery must be signed by the current key".into(),
+        });
+    }
+
+    let hashed_password = bcrypt::hash(&data.new_password, 10).unwrap();
+    let result = collection
+        .update_one(
+            doc! {"reg_no": &user.reg_no, "public_key": &public_key},
+            doc! {"$set": {"password": hashed_password, "key_derivation": null}},
+            None,
+        )
+        .await;
+    match result {
+        Ok(result) if result.modified_count == 1 => {}
+        Ok(_) => {
+            return Err(UserError::ValidationError {
+                content: "Key was rotated or revoked in the meantime".into(),
+            })
+        }
+        Err(e) => {
+            return Err(UserError::InternalError {
+                content: e.to_string(),
+            })
+        }
+    }
+    let key: Hmac<Sha256> = hmac::Mac::new_from_slice(b"USER_SECRETE").unwrap();
+    let mut claims: BTreeMap<String, String> = BTreeMap::new();
+    claims.insert("reg_no".into(), user.reg_no.clone());
+    let token = claims.sign_with_key(&key);
+    Ok(json!({
+        "message": "account recovered",
+        "token": token.unwrap(),
+        "public_key": public_key,
+    })
+    .to_string())
+}
+
 pub async fn admin_login(
     client: web::Data<Client>,
     data: web::Json<AdminLoginDTO>,
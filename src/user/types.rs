@@ -6,6 +6,8 @@ use actix_web::{
 use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
 
+use super::models::KeyDerivation;
+
 #[derive(Debug, Display, Error)]
 pub enum UserError {
     #[display(fmt = "{}", content)]
@@ -42,3 +44,55 @@ pub struct AdminLoginDTO {
     pub password: String,
 }
 
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RotateKeyDTO {
+    pub reg_no: String,
+    pub password: String,
+    pub new_public_key: String,
+    /// The current key's signature over `rotation_message`, base64 encoded.
+    pub signature: String,
+    /// For a server-derived key, how the new key derives from the password.
+    #[serde(default)]
+    pub key_derivation: Option<KeyDerivation>,
+}
+
+/// Revokes a user's current key, optionally handing them a replacement checked
+/// out of band, since a revoked key cannot sign its own rotation.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RevokeKeyDTO {
+    pub reg_no: String,
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+/// Exports a server-derived key sealed under `passphrase`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExportKeyDTO {
+    pub reg_no: String,
+    pub password: String,
+    pub passphrase: String,
+}
+
+/// Shows the user holds their current key, opened from a keystore on their
+/// device; neither the keystore nor its passphrase is sent.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImportKeyDTO {
+    pub reg_no: String,
+    pub password: String,
+    pub public_key: String,
+    pub issued_at: i64,
+    /// The key's signature over `import_message`, base64 encoded.
+    pub signature: String,
+}
+
+/// Sets a new password for a user who proves they hold the key from their
+/// recovery phrase.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecoverDTO {
+    pub reg_no: String,
+    pub new_password: String,
+    pub issued_at: i64,
+    /// The current key's signature over `recovery_message`, base64 encoded.
+    pub signature: String,
+}
@@ -1,10 +1,78 @@
-use ed25519_compact::{KeyPair, PublicKey, Seed};
+use argon2::{Algorithm, Argon2, Params, Version};
+use bip39::Mnemonic;
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use ed25519_compact::{KeyPair, PublicKey, Seed, Signature};
 use jwt::{FromBase64, ToBase64};
+use rand::{rngs::OsRng, RngCore};
 
-pub fn generate_keys(password: &String, reg_no: &String) -> KeyPair {
+use crate::blockchain::encoding::{
+    CanonicalEncoder, KEY_IMPORT_DOMAIN, KEY_RECOVERY_DOMAIN, KEY_ROTATION_DOMAIN,
+};
+
+use super::models::{KeyDerivation, Keystore, KeystoreCipher, KeystoreKdf};
+
+/// Argon2id v1.3 over the password and a random per-user salt, with 19 MiB of
+/// memory, 2 passes and 1 lane, giving the 32-byte ed25519 seed.
+pub const KDF_ARGON2ID: u8 = 1;
+/// Version new server-derived keys are made with.
+pub const KDF_VERSION: u8 = KDF_ARGON2ID;
+pub const KEY_SALT_BYTES: usize = 16;
+
+pub const KEYSTORE_VERSION: u8 = 1;
+const KEYSTORE_MEMORY: u32 = 19 * 1024;
+const KEYSTORE_ITERATIONS: u32 = 2;
+// keystores can come from anywhere, so cap what opening one may cost
+const KEYSTORE_MAX_MEMORY: u32 = 256 * 1024;
+const KEYSTORE_MAX_ITERATIONS: u32 = 16;
+const KEYSTORE_MAX_PARALLELISM: u32 = 8;
+
+/// How far a signed key request's `issued_at` may be from the server's clock, in seconds.
+pub const SIGNED_REQUEST_WINDOW: i64 = 300;
+
+/// Whether signup may derive a key from the password when the client sends no
+/// public key, for deployments whose voters cannot hold their own keys.
+pub fn server_key_derivation() -> bool {
+    matches!(
+        std::env::var("SERVER_KEY_DERIVATION").as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// A fresh salt for `KDF_VERSION`.
+pub fn new_key_derivation() -> KeyDerivation {
+    let mut salt = [0u8; KEY_SALT_BYTES];
+    OsRng.fill_bytes(&mut salt);
+    KeyDerivation {
+        version: KDF_VERSION,
+        salt: hex::encode(salt),
+    }
+}
+
+/// Re-derives a server-derived key; `None` for an unknown version or a malformed salt.
+pub fn derive_keys(password: &str, derivation: &KeyDerivation) -> Option<KeyPair> {
+    match derivation.version {
+        KDF_ARGON2ID => {
+            let salt = hex::decode(&derivation.salt).ok()?;
+            let params = Params::new(19 * 1024, 2, 1, Some(Seed::BYTES)).ok()?;
+            let mut seed = [0u8; Seed::BYTES];
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), &salt, &mut seed)
+                .ok()?;
+            Some(KeyPair::from_seed(Seed::new(seed)))
+        }
+        _ => None,
+    }
+}
+
+/// The key accounts got before `KDF_ARGON2ID`: the bytes of `reg_no + password`
+/// padded with `49`. Only used to recognise those accounts and migrate them.
+pub fn legacy_keys(password: &str, reg_no: &str) -> KeyPair {
     let mut seed = [0u8; Seed::BYTES];
-    let mut comb_string = reg_no.clone();
-    comb_string.push_str(password.clone().as_str());
+    let mut comb_string = reg_no.to_string();
+    comb_string.push_str(password);
     let comb_bytes = comb_string.as_bytes();
 
     for (index, pos) in seed.iter_mut().enumerate() {
@@ -18,8 +86,208 @@ pub fn generate_keys(password: &String, reg_no: &String) -> KeyPair {
     KeyPair::from_seed(seed)
 }
 
-pub fn pk_from_string(pk: &String) -> PublicKey {
+fn keystore_key(passphrase: &str, kdf: &KeystoreKdf) -> Option<[u8; 32]> {
+    if kdf.name != "argon2id"
+        || kdf.memory > KEYSTORE_MAX_MEMORY
+        || kdf.iterations > KEYSTORE_MAX_ITERATIONS
+        || kdf.parallelism > KEYSTORE_MAX_PARALLELISM
+    {
+        return None;
+    }
+    let salt = hex::decode(&kdf.salt).ok()?;
+    let params = Params::new(kdf.memory, kdf.iterations, kdf.parallelism, Some(32)).ok()?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .ok()?;
+    Some(key)
+}
+
+/// Seals `pair`'s seed under `passphrase`.
+pub fn seal_keystore(pair: &KeyPair, passphrase: &str) -> Keystore {
+    let mut salt = [0u8; KEY_SALT_BYTES];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let kdf = KeystoreKdf {
+        name: "argon2id".into(),
+        memory: KEYSTORE_MEMORY,
+        iterations: KEYSTORE_ITERATIONS,
+        parallelism: 1,
+        salt: hex::encode(salt),
+    };
+    let public_key = pair.pk.to_base64().unwrap().to_string();
+    let cipher = ChaCha20Poly1305::new(&keystore_key(passphrase, &kdf).unwrap().into());
+    let seed = pair.sk.seed();
+    let payload = Payload {
+        msg: seed.as_ref(),
+        aad: public_key.as_bytes(),
+    };
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), payload).unwrap();
+    Keystore {
+        version: KEYSTORE_VERSION,
+        public_key,
+        kdf,
+        cipher: KeystoreCipher {
+            name: "chacha20-poly1305".into(),
+            nonce: hex::encode(nonce),
+        },
+        ciphertext: hex::encode(ciphertext),
+    }
+}
+
+/// The key pair in `keystore`; `None` for a wrong passphrase, a tampered or
+/// unknown keystore, or one whose seed is not for its `public_key`.
+// run by clients importing a keystore, never by the server
+#[allow(dead_code)]
+pub fn open_keystore(keystore: &Keystore, passphrase: &str) -> Option<KeyPair> {
+    if keystore.version != KEYSTORE_VERSION || keystore.cipher.name != "chacha20-poly1305" {
+        return None;
+    }
+    let nonce = hex::decode(&keystore.cipher.nonce).ok()?;
+    if nonce.len() != 12 {
+        return None;
+    }
+    let ciphertext = hex::decode(&keystore.ciphertext).ok()?;
+    let cipher = ChaCha20Poly1305::new(&keystore_key(passphrase, &keystore.kdf)?.into());
+    let payload = Payload {
+        msg: &ciphertext,
+        aad: keystore.public_key.as_bytes(),
+    };
+    let seed = cipher.decrypt(Nonce::from_slice(&nonce), payload).ok()?;
+    let pair = KeyPair::from_seed(Seed::from_slice(&seed).ok()?);
+    if pair.pk.to_base64().ok()? != keystore.public_key {
+        return None;
+    }
+    Some(pair)
+}
+
+/// The 24-word English BIP39 phrase whose entropy is `pair`'s seed.
+pub fn seed_mnemonic(pair: &KeyPair) -> String {
+    Mnemonic::from_entropy(pair.sk.seed().as_ref())
+        .unwrap()
+        .to_string()
+}
+
+/// What a user signs with the key from their recovery phrase to set a new password.
+pub fn recovery_message(reg_no: &str, public_key: &str, new_password: &str, issued_at: i64) -> Vec<u8> {
+    CanonicalEncoder::new(KEY_RECOVERY_DOMAIN, 1)
+        .string(reg_no)
+        .string(public_key)
+        .string(new_password)
+        .i64(issued_at)
+        .finish()
+}
+
+/// What a user signs with the key opened from a keystore to show they hold it.
+pub fn import_message(reg_no: &str, public_key: &str, issued_at: i64) -> Vec<u8> {
+    CanonicalEncoder::new(KEY_IMPORT_DOMAIN, 1)
+        .string(reg_no)
+        .string(public_key)
+        .i64(issued_at)
+        .finish()
+}
+
+/// Whether a signed request issued at `issued_at` may still be acted on at `now`.
+pub fn request_is_fresh(issued_at: i64, now: i64) -> bool {
+    (now - issued_at).abs() <= SIGNED_REQUEST_WINDOW
+}
+
+/// Whether `signature` is `public_key`'s base64 encoded signature over `message`.
+pub fn signature_is_valid(public_key: &String, message: &[u8], signature: &str) -> bool {
+    let signature: Result<Vec<u8>, jwt::Error> = FromBase64::from_base64(signature);
+    match (pk_from_string(public_key), signature) {
+        (Some(pk), Ok(sig)) => match Signature::from_slice(&sig) {
+            Ok(sig) => pk.verify(message, &sig).is_ok(),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// What a user signs with `old_key` to hand over to `new_key`.
+pub fn rotation_message(reg_no: &str, old_key: &str, new_key: &str) -> Vec<u8> {
+    CanonicalEncoder::new(KEY_ROTATION_DOMAIN, 1)
+        .string(reg_no)
+        .string(old_key)
+        .string(new_key)
+        .finish()
+}
+
+pub fn pk_from_string(pk: &String) -> Option<PublicKey> {
     let pk_dec: Result<Vec<u8>, jwt::Error> = FromBase64::from_base64(&pk);
-    let pk_conv = PublicKey::from_slice(&pk_dec.unwrap());
-    pk_conv.unwrap()
+    PublicKey::from_slice(&pk_dec.ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public_key(pair: &KeyPair) -> String {
+        pair.pk.to_base64().unwrap().to_string()
+    }
+
+    fn sign(pair: &KeyPair, message: &[u8]) -> String {
+        pair.sk.sign(message, None).to_vec().to_base64().unwrap().to_string()
+    }
+
+    #[test]
+    fn mnemonic_restores_the_key() {
+        let pair = derive_keys("hunter2", &new_key_derivation()).unwrap();
+        let phrase = seed_mnemonic(&pair);
+        assert_eq!(phrase.split_whitespace().count(), 24);
+
+        let seed = Mnemonic::parse(&phrase).unwrap().to_entropy();
+        let restored = KeyPair::from_seed(Seed::from_slice(&seed).unwrap());
+        assert_eq!(restored.pk, pair.pk);
+    }
+
+    #[test]
+    fn derived_keys_depend_on_password_and_salt() {
+        let derivation = new_key_derivation();
+        let pair = derive_keys("hunter2", &derivation).unwrap();
+
+        assert_eq!(derive_keys("hunter2", &derivation).unwrap().pk, pair.pk);
+        assert_ne!(derive_keys("hunter3", &derivation).unwrap().pk, pair.pk);
+        assert_ne!(derive_keys("hunter2", &new_key_derivation()).unwrap().pk, pair.pk);
+    }
+
+    #[test]
+    fn recovery_signature_covers_the_request() {
+        let pair = KeyPair::from_seed(Seed::generate());
+        let other = KeyPair::from_seed(Seed::generate());
+        let key = public_key(&pair);
+        let message = recovery_message("REG/001", &key, "new-password", 1_700_000_000);
+        let signature = sign(&pair, &message);
+
+        assert!(signature_is_valid(&key, &message, &signature));
+        let changed = recovery_message("REG/001", &key, "other-password", 1_700_000_000);
+        assert!(!signature_is_valid(&key, &changed, &signature));
+        let later = recovery_message("REG/001", &key, "new-password", 1_700_000_001);
+        assert!(!signature_is_valid(&key, &later, &signature));
+        assert!(!signature_is_valid(&key, &message, &sign(&other, &message)));
+        assert!(!signature_is_valid(&key, &message, "not base64!"));
+    }
+
+    #[test]
+    fn signed_requests_expire_after_the_window() {
+        let now = 1_700_000_000;
+        assert!(request_is_fresh(now, now));
+        assert!(request_is_fresh(now - SIGNED_REQUEST_WINDOW, now));
+        assert!(request_is_fresh(now + SIGNED_REQUEST_WINDOW, now));
+        assert!(!request_is_fresh(now - SIGNED_REQUEST_WINDOW - 1, now));
+        assert!(!request_is_fresh(now + SIGNED_REQUEST_WINDOW + 1, now));
+    }
+
+    #[test]
+    fn keystore_opens_only_with_its_passphrase() {
+        let pair = KeyPair::from_seed(Seed::generate());
+        let keystore = seal_keystore(&pair, "correct horse");
+
+        assert_eq!(open_keystore(&keystore, "correct horse").unwrap().pk, pair.pk);
+        assert!(open_keystore(&keystore, "wrong horse").is_none());
+        let mut relabelled = keystore.clone();
+        relabelled.public_key = public_key(&KeyPair::from_seed(Seed::generate()));
+        assert!(open_keystore(&relabelled, "correct horse").is_none());
+    }
 }